@@ -0,0 +1,247 @@
+use std::{
+    error::Error,
+    io::{Cursor, Read},
+};
+
+use zip::ZipArchive;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Archive {
+    ZIP,
+    TAR,
+}
+
+/// Recognises zip and tar payloads by their magic bytes. Compressed tarballs
+/// are only recognised once decompressed.
+pub fn detect_archive(bytes: &[u8]) -> Option<Archive> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        Some(Archive::ZIP)
+    } else if bytes.len() > 262 && &bytes[257..262] == b"ustar" {
+        Some(Archive::TAR)
+    } else {
+        None
+    }
+}
+
+/// Path and contents of an archive entry.
+type Entry = (String, Vec<u8>);
+
+/// Paths of the regular files of the archive, without reading their data.
+fn list_members(archive: Archive, bytes: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names = Vec::new();
+    match archive {
+        Archive::ZIP => {
+            let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+            for i in 0..zip.len() {
+                let file = zip.by_index_raw(i)?;
+                if !file.is_dir() {
+                    names.push(file.name().to_string());
+                }
+            }
+        }
+        Archive::TAR => {
+            let mut tar = tar::Archive::new(bytes);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    names.push(entry.path()?.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Contents of the `index`-th regular file of the archive, the only one
+/// that is decompressed.
+fn read_member(archive: Archive, bytes: &[u8], index: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    match archive {
+        Archive::ZIP => {
+            let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+            let mut files = 0;
+            for i in 0..zip.len() {
+                if zip.by_index_raw(i)?.is_dir() {
+                    continue;
+                }
+                if files == index {
+                    zip.by_index(i)?.read_to_end(&mut data)?;
+                    return Ok(data);
+                }
+                files += 1;
+            }
+        }
+        Archive::TAR => {
+            let mut tar = tar::Archive::new(bytes);
+            let files = tar
+                .entries()?
+                .filter(|entry| {
+                    entry
+                        .as_ref()
+                        .map_or(true, |entry| entry.header().entry_type().is_file())
+                })
+                .nth(index);
+            if let Some(entry) = files {
+                entry?.read_to_end(&mut data)?;
+                return Ok(data);
+            }
+        }
+    }
+    Err("Archive changed while reading it".into())
+}
+
+/// Extracts one file out of an archive, returning its path and contents.
+/// Only the names of the other entries are read.
+///
+/// `member` matches the full path of the entry, or its file name when only
+/// one entry has it. Without `member` the archive must hold a single file.
+pub fn extract_member(
+    archive: Archive,
+    bytes: &[u8],
+    member: Option<&str>,
+) -> Result<Entry, Box<dyn Error>> {
+    let mut entries = list_members(archive, bytes)?;
+    let names = || entries.join(", ");
+
+    let index = match member {
+        Some(member) => {
+            let member = member.trim_start_matches("./");
+            let by_name = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| name.rsplit('/').next() == Some(member))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            entries
+                .iter()
+                .position(|name| name.trim_start_matches("./") == member)
+                .or(match by_name.as_slice() {
+                    [i] => Some(*i),
+                    _ => None,
+                })
+                .ok_or_else(|| {
+                    format!("Archive has no member {}, available: {}", member, names())
+                })?
+        }
+        None => match entries.len() {
+            0 => return Err("Archive is empty".into()),
+            1 => 0,
+            _ => {
+                return Err(format!(
+                    "Archive has several members, pick one with MEMBER: {}",
+                    names()
+                )
+                .into())
+            }
+        },
+    };
+
+    let data = read_member(archive, bytes, index)?;
+    Ok((entries.swap_remove(index), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_detect_archive() {
+        assert_eq!(
+            detect_archive(&zip_of(&[("a.csv", "a\n")])),
+            Some(Archive::ZIP)
+        );
+        assert_eq!(
+            detect_archive(&tar_of(&[("a.csv", "a\n")])),
+            Some(Archive::TAR)
+        );
+        assert_eq!(detect_archive(b"a,b\n1,2\n"), None);
+    }
+
+    #[test]
+    fn test_extract_single_member() {
+        let bytes = zip_of(&[("data/cities.csv", "name\nChicago\n")]);
+        let (name, data) = extract_member(Archive::ZIP, &bytes, None).unwrap();
+        assert_eq!(name, "data/cities.csv");
+        assert_eq!(data, b"name\nChicago\n");
+    }
+
+    #[test]
+    fn test_extract_named_member() {
+        let files = [("data/a.csv", "a\n1\n"), ("data/b.csv", "b\n2\n")];
+        for (archive, bytes) in [
+            (Archive::ZIP, zip_of(&files)),
+            (Archive::TAR, tar_of(&files)),
+        ] {
+            let (_, data) = extract_member(archive, &bytes, Some("data/b.csv")).unwrap();
+            assert_eq!(data, b"b\n2\n");
+            let (name, _) = extract_member(archive, &bytes, Some("a.csv")).unwrap();
+            assert_eq!(name, "data/a.csv");
+        }
+    }
+
+    #[test]
+    fn test_list_members_skips_directories() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("data/", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("data/a.csv", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"a\n1\n").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(list_members(Archive::ZIP, &bytes).unwrap(), ["data/a.csv"]);
+        assert_eq!(read_member(Archive::ZIP, &bytes, 0).unwrap(), b"a\n1\n");
+        let (name, data) = extract_member(Archive::ZIP, &bytes, None).unwrap();
+        assert_eq!(
+            (name.as_str(), data.as_slice()),
+            ("data/a.csv", &b"a\n1\n"[..])
+        );
+    }
+
+    #[test]
+    fn test_extract_member_errors() {
+        let bytes = zip_of(&[("a.csv", "a\n"), ("b.csv", "b\n")]);
+        assert_eq!(
+            extract_member(Archive::ZIP, &bytes, Some("c.csv"))
+                .unwrap_err()
+                .to_string(),
+            "Archive has no member c.csv, available: a.csv, b.csv"
+        );
+        assert_eq!(
+            extract_member(Archive::ZIP, &bytes, None)
+                .unwrap_err()
+                .to_string(),
+            "Archive has several members, pick one with MEMBER: a.csv, b.csv"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, mem};
 
 #[derive(Debug, PartialEq)]
 pub struct ParsedArgs {
@@ -16,14 +16,9 @@ pub fn parse_args(args: Vec<String>) -> ParsedArgs {
             let key = key_part.trim().to_uppercase();
             let value = value_part[1..].trim(); // skip '='
 
-            let clean_value = value.trim_matches(|c| c == '\'' || c == '"').to_string();
-
-            named.insert(key, clean_value);
+            named.insert(key, unquote(value));
         } else {
-            let val = arg
-                .trim()
-                .trim_matches(|c| c == '\'' || c == '"')
-                .to_string();
+            let val = unquote(arg.trim());
             if !val.is_empty() {
                 positional.push(val);
             }
@@ -33,6 +28,53 @@ pub fn parse_args(args: Vec<String>) -> ParsedArgs {
     ParsedArgs { named, positional }
 }
 
+/// Splits a list of options such as `delimiter=';', skip_rows=1` at the
+/// commas outside quotes, as SQLite splits the arguments of a module.
+pub fn split_options(options: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for c in options.chars() {
+        match quote {
+            None if c == ',' => {
+                parts.push(mem::take(&mut current));
+                continue;
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            Some(q) if c == q => quote = None,
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+/// Removes one pair of matching quotes around `value`, turning doubled
+/// quotes inside into single ones as SQL does, so `'"'` and `''''` are a
+/// double and a single quote.
+fn unquote(value: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.replace(&format!("{0}{0}", quote), &quote.to_string());
+        }
+    }
+    value.to_string()
+}
+
+/// Parses a boolean option value such as `true`, `0` or `yes`.
+pub fn parse_bool(value: &str) -> Result<bool, Box<dyn Error>> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!("Invalid boolean value: {}", value).into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,4 +171,36 @@ mod tests {
         assert_eq!(result.named, expected_named);
         assert_eq!(result.positional, expected_positional);
     }
+
+    #[test]
+    fn test_quoted_quotes() {
+        let input = vec![
+            "quote='\"'".to_string(),
+            "escape=''''".to_string(),
+            "empty=''".to_string(),
+        ];
+        let result = parse_args(input);
+        assert_eq!(result.named["QUOTE"], "\"");
+        assert_eq!(result.named["ESCAPE"], "'");
+        assert_eq!(result.named["EMPTY"], "");
+    }
+
+    #[test]
+    fn test_split_options() {
+        assert_eq!(
+            split_options("delimiter=';', quote = '''', headers=\"a: 1, 2\",,"),
+            ["delimiter=';'", " quote = ''''", " headers=\"a: 1, 2\""]
+        );
+        assert!(split_options(" ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert!(parse_bool("true").unwrap());
+        assert!(parse_bool(" YES ").unwrap());
+        assert!(parse_bool("1").unwrap());
+        assert!(!parse_bool("false").unwrap());
+        assert!(!parse_bool("Off").unwrap());
+        assert!(parse_bool("maybe").is_err());
+    }
 }
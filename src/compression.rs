@@ -0,0 +1,246 @@
+use std::{error::Error, io::Read};
+
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    NONE,
+    GZIP,
+    ZSTD,
+    BZIP2,
+    XZ,
+}
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::NONE => "NONE",
+            Compression::GZIP => "GZIP",
+            Compression::ZSTD => "ZSTD",
+            Compression::BZIP2 => "BZIP2",
+            Compression::XZ => "XZ",
+        }
+    }
+
+    fn magic(&self) -> &'static [u8] {
+        match self {
+            Compression::NONE => b"",
+            Compression::GZIP => &[0x1f, 0x8b],
+            Compression::ZSTD => &[0x28, 0xb5, 0x2f, 0xfd],
+            Compression::BZIP2 => b"BZh",
+            Compression::XZ => &[0xfd, b'7', b'z', b'X', b'Z', 0x00],
+        }
+    }
+}
+
+const COMPRESSIONS: [Compression; 4] = [
+    Compression::GZIP,
+    Compression::ZSTD,
+    Compression::BZIP2,
+    Compression::XZ,
+];
+
+/// Parses the `COMPRESSION` option. `AUTO` maps to `None`, leaving the choice
+/// to [`detect_compression`].
+pub fn get_compression(opt: &str) -> Result<Option<Compression>, Box<dyn Error>> {
+    match opt.to_uppercase().as_str() {
+        "AUTO" => Ok(None),
+        "NONE" => Ok(Some(Compression::NONE)),
+        "GZIP" | "GZ" => Ok(Some(Compression::GZIP)),
+        "ZSTD" | "ZST" => Ok(Some(Compression::ZSTD)),
+        "BZIP2" | "BZ2" => Ok(Some(Compression::BZIP2)),
+        "XZ" => Ok(Some(Compression::XZ)),
+        _ => Err(format!("Unknown compression: {}", opt).into()),
+    }
+}
+
+fn from_content_encoding(encoding: &str) -> Option<Compression> {
+    match encoding.trim().to_lowercase().as_str() {
+        "gzip" | "x-gzip" => Some(Compression::GZIP),
+        "zstd" => Some(Compression::ZSTD),
+        "bzip2" | "x-bzip2" => Some(Compression::BZIP2),
+        "xz" | "x-xz" => Some(Compression::XZ),
+        _ => None,
+    }
+}
+
+fn from_extension(file_name: &str) -> Option<Compression> {
+    let (_, ext) = file_name.rsplit_once('.')?;
+    match ext.to_lowercase().as_str() {
+        "gz" | "gzip" => Some(Compression::GZIP),
+        "zst" | "zstd" => Some(Compression::ZSTD),
+        "bz2" => Some(Compression::BZIP2),
+        "xz" => Some(Compression::XZ),
+        _ => None,
+    }
+}
+
+/// Picks the compression of a payload from its `Content-Encoding`, then the
+/// file extension, then the magic bytes. A hint is only trusted when the
+/// payload starts with the matching magic bytes, so a server that already
+/// decoded a `.gz` file does not break the read.
+pub fn detect_compression(
+    file_name: Option<&str>,
+    content_encoding: Option<&str>,
+    bytes: &[u8],
+) -> Compression {
+    let hints = [
+        content_encoding.and_then(from_content_encoding),
+        file_name.and_then(from_extension),
+    ];
+    hints
+        .into_iter()
+        .flatten()
+        .chain(COMPRESSIONS)
+        .find(|c| bytes.starts_with(c.magic()))
+        .unwrap_or(Compression::NONE)
+}
+
+/// Strips a compression extension, `events.csv.gz` becomes `events.csv`.
+pub fn strip_compression_extension(file_name: &str) -> &str {
+    match file_name.rsplit_once('.') {
+        Some((stem, _)) if from_extension(file_name).is_some() => stem,
+        _ => file_name,
+    }
+}
+
+pub fn decompress(compression: Compression, bytes: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    match compression {
+        Compression::NONE => Ok(bytes),
+        _ => decode(compression, &bytes),
+    }
+}
+
+/// Detects the compression of a payload with [`detect_compression`] and
+/// decompresses it. When only the magic bytes point at a compression, with
+/// no extension or `Content-Encoding` saying so, a payload that fails to
+/// decode is plain data that happens to start like a compressed stream, a
+/// CSV whose first field is `BZh` for example, and is returned as is.
+pub fn decompress_detected(
+    file_name: Option<&str>,
+    content_encoding: Option<&str>,
+    bytes: Vec<u8>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let compression = detect_compression(file_name, content_encoding, &bytes);
+    let hinted = [
+        content_encoding.and_then(from_content_encoding),
+        file_name.and_then(from_extension),
+    ]
+    .contains(&Some(compression));
+    if compression == Compression::NONE || hinted {
+        return decompress(compression, bytes);
+    }
+    Ok(decode(compression, &bytes).unwrap_or(bytes))
+}
+
+fn decode(compression: Compression, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut out = Vec::new();
+    let result = match compression {
+        Compression::NONE => return Ok(bytes.to_vec()),
+        Compression::GZIP => MultiGzDecoder::new(bytes).read_to_end(&mut out),
+        Compression::ZSTD => zstd::stream::read::Decoder::new(bytes)
+            .and_then(|mut decoder| decoder.read_to_end(&mut out)),
+        Compression::BZIP2 => BzDecoder::new(bytes).read_to_end(&mut out),
+        Compression::XZ => XzDecoder::new(bytes).read_to_end(&mut out),
+    };
+    result.map_err(|e| format!("{} decompression error: {}", compression.as_str(), e))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DATA: &[u8] = b"name,pop\nChicago,2705627\nDallas,1219399\n";
+
+    fn compress(compression: Compression) -> Vec<u8> {
+        match compression {
+            Compression::NONE => DATA.to_vec(),
+            Compression::GZIP => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(DATA).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::ZSTD => zstd::stream::encode_all(DATA, 0).unwrap(),
+            Compression::BZIP2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(DATA).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::XZ => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(DATA).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_compression() {
+        assert_eq!(get_compression("auto").unwrap(), None);
+        assert_eq!(get_compression("gz").unwrap(), Some(Compression::GZIP));
+        assert_eq!(get_compression("Zstd").unwrap(), Some(Compression::ZSTD));
+        assert_eq!(get_compression("none").unwrap(), Some(Compression::NONE));
+        assert!(get_compression("lzma").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for compression in COMPRESSIONS {
+            let bytes = compress(compression);
+            assert_eq!(detect_compression(None, None, &bytes), compression);
+            assert_eq!(decompress(compression, bytes).unwrap(), DATA);
+        }
+    }
+
+    #[test]
+    fn test_detect_compression_hints() {
+        let gzip = compress(Compression::GZIP);
+        assert_eq!(
+            detect_compression(Some("data.csv.gz"), Some("gzip"), &gzip),
+            Compression::GZIP
+        );
+        // Already decoded payload behind a `.gz` name.
+        assert_eq!(
+            detect_compression(Some("data.csv.gz"), Some("gzip"), DATA),
+            Compression::NONE
+        );
+    }
+
+    #[test]
+    fn test_strip_compression_extension() {
+        assert_eq!(strip_compression_extension("events.csv.gz"), "events.csv");
+        assert_eq!(
+            strip_compression_extension("events.jsonl.zst"),
+            "events.jsonl"
+        );
+        assert_eq!(strip_compression_extension("events.csv"), "events.csv");
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let err = decompress(Compression::GZIP, DATA.to_vec()).unwrap_err();
+        assert!(err.to_string().starts_with("GZIP decompression error"));
+    }
+
+    #[test]
+    fn test_decompress_detected_plain_magic() {
+        let csv = b"BZh,pop\nChicago,2705627\n".to_vec();
+        assert_eq!(
+            decompress_detected(Some("data.csv"), None, csv.clone()).unwrap(),
+            csv
+        );
+        assert_eq!(
+            decompress_detected(None, None, compress(Compression::XZ)).unwrap(),
+            DATA
+        );
+        // A hint that the magic bytes agree with is not second-guessed.
+        assert!(decompress_detected(Some("data.csv.bz2"), None, csv).is_err());
+    }
+}
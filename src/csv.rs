@@ -0,0 +1,547 @@
+use std::{collections::HashMap, error::Error, io::Cursor, mem};
+
+use polars::prelude::{
+    CsvParseOptions, CsvReadOptions, DataFrame, NullValues, PlSmallStr, PolarsError, PolarsResult,
+    SerReader,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::args::parse_bool;
+
+/// Bytes from the start of the data inspected by `SNIFF`.
+pub const SNIFF_SAMPLE: usize = 64 * 1024;
+/// Delimiters `SNIFF` tries, the earlier ones win ties.
+const DELIMITERS: [char; 5] = [',', ';', '\t', '|', ':'];
+
+/// Parts of the dialect `SNIFF` infers from the data.
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sniffed {
+    DELIMITER,
+    QUOTE,
+    HEADER,
+    LINE_TERMINATOR,
+}
+
+/// Dialect inferred from the start of the data. It is recorded in
+/// `_metadata` so later reloads read the data the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CsvDialect {
+    pub delimiter: char,
+    pub quote: Option<char>,
+    pub header: bool,
+    pub line_terminator: String,
+}
+
+impl CsvDialect {
+    pub fn serialize(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn deserialize(value: &str) -> Result<CsvDialect, Box<dyn Error>> {
+        serde_json::from_str(value).map_err(|e| format!("Invalid CSV dialect: {}", e).into())
+    }
+}
+
+/// Dialect of a CSV source, mapped onto Polars' `CsvReadOptions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOpts {
+    pub delimiter: u8,
+    /// `None` disables quoting.
+    pub quote: Option<u8>,
+    pub header: bool,
+    pub skip_rows: usize,
+    pub comment_prefix: Option<String>,
+    pub null_values: Vec<String>,
+    pub column_names: Vec<String>,
+    /// End of line character, `\r\n` is read with `\n`.
+    pub eol: u8,
+    /// With `SNIFF`, the parts of the dialect not given explicitly.
+    pub sniff: Vec<Sniffed>,
+    /// The sniffed dialect, once known.
+    pub dialect: Option<CsvDialect>,
+}
+
+impl Default for CsvOpts {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: Some(b'"'),
+            header: true,
+            skip_rows: 0,
+            comment_prefix: None,
+            null_values: Vec::new(),
+            column_names: Vec::new(),
+            eol: b'\n',
+            sniff: Vec::new(),
+            dialect: None,
+        }
+    }
+}
+
+impl CsvOpts {
+    /// Whether the dialect is still to be sniffed from the data.
+    pub fn needs_sniffing(&self) -> bool {
+        !self.sniff.is_empty() && self.dialect.is_none()
+    }
+
+    /// Takes the sniffed parts of the dialect from `dialect`, the options
+    /// given explicitly are kept.
+    pub fn with_dialect(mut self, dialect: CsvDialect) -> CsvOpts {
+        let byte = |c: char, default: u8| u8::try_from(c).unwrap_or(default);
+        for part in &self.sniff {
+            match part {
+                Sniffed::DELIMITER => self.delimiter = byte(dialect.delimiter, self.delimiter),
+                Sniffed::QUOTE => self.quote = dialect.quote.map(|q| byte(q, b'"')),
+                Sniffed::HEADER => self.header = dialect.header,
+                Sniffed::LINE_TERMINATOR => {
+                    self.eol = match dialect.line_terminator.as_str() {
+                        "\r" => b'\r',
+                        _ => b'\n',
+                    }
+                }
+            }
+        }
+        self.dialect = Some(dialect);
+        self
+    }
+}
+
+/// Builds the [`CsvOpts`] from the named arguments of the virtual table:
+///
+/// - `DELIMITER`: a single character, `\t` or `tab` for tabs
+/// - `QUOTE`: a single character, empty or `none` to disable quoting
+/// - `HEADER`: whether the first row holds the column names, `true` by default
+/// - `SKIP_ROWS`: lines to skip before the header or the first row
+/// - `COMMENT_PREFIX`: lines starting with it are ignored
+/// - `NULL_VALUES`: `'NA,n/a,-'`, values read as NULL in every column
+/// - `COLUMN_NAMES`: `'id,name,price'`, renames the leading columns
+/// - `SNIFF`: infers the delimiter, quote, header and line terminator from
+///   the data, unless given explicitly
+pub fn get_csv_opts(named: &HashMap<String, String>) -> Result<CsvOpts, Box<dyn Error>> {
+    let defaults = CsvOpts::default();
+    let delimiter = match named.get("DELIMITER") {
+        Some(d) => parse_delimiter(d)?,
+        None => defaults.delimiter,
+    };
+    let quote = match named.get("QUOTE") {
+        Some(q) => parse_quote(q)?,
+        None => defaults.quote,
+    };
+    let header = named
+        .get("HEADER")
+        .map_or(Ok(defaults.header), |h| parse_bool(h))?;
+    let skip_rows = match named.get("SKIP_ROWS") {
+        Some(s) => s
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Not a valid SKIP_ROWS value: {}", s))?,
+        None => defaults.skip_rows,
+    };
+    let comment_prefix = named
+        .get("COMMENT_PREFIX")
+        .filter(|prefix| !prefix.is_empty())
+        .cloned();
+    let null_values = named
+        .get("NULL_VALUES")
+        .map(|values| split_list(values))
+        .unwrap_or_default();
+    let column_names = named
+        .get("COLUMN_NAMES")
+        .map(|names| split_list(names))
+        .unwrap_or_default();
+    // The line terminator has no option of its own, it is always sniffed.
+    let sniff = match named.get("SNIFF").map_or(Ok(false), |s| parse_bool(s))? {
+        true => [
+            ("DELIMITER", Sniffed::DELIMITER),
+            ("QUOTE", Sniffed::QUOTE),
+            ("HEADER", Sniffed::HEADER),
+        ]
+        .into_iter()
+        .filter(|(name, _)| !named.contains_key(*name))
+        .map(|(_, part)| part)
+        .chain([Sniffed::LINE_TERMINATOR])
+        .collect(),
+        false => Vec::new(),
+    };
+
+    Ok(CsvOpts {
+        delimiter,
+        quote,
+        header,
+        skip_rows,
+        comment_prefix,
+        null_values,
+        column_names,
+        sniff,
+        ..defaults
+    })
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, Box<dyn Error>> {
+    match value {
+        "\\t" | "\t" => Ok(b'\t'),
+        v if v.eq_ignore_ascii_case("tab") => Ok(b'\t'),
+        v if v.len() == 1 && v.is_ascii() => Ok(v.as_bytes()[0]),
+        _ => Err(format!("Not a valid DELIMITER value: {}", value).into()),
+    }
+}
+
+fn parse_quote(value: &str) -> Result<Option<u8>, Box<dyn Error>> {
+    match value {
+        "" => Ok(None),
+        v if v.eq_ignore_ascii_case("none") => Ok(None),
+        v if v.len() == 1 && v.is_ascii() => Ok(Some(v.as_bytes()[0])),
+        _ => Err(format!("Not a valid QUOTE value: {}", value).into()),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parses `data` as CSV with the dialect in `opts`.
+pub fn read_csv(data: Vec<u8>, opts: &CsvOpts) -> PolarsResult<DataFrame> {
+    let null_values = Some(opts.null_values.clone())
+        .filter(|values| !values.is_empty())
+        .map(|values| NullValues::AllColumns(values.into_iter().map(Into::into).collect()));
+    let parse_options = CsvParseOptions::default()
+        .with_separator(opts.delimiter)
+        .with_quote_char(opts.quote)
+        .with_eol_char(opts.eol)
+        .with_comment_prefix(opts.comment_prefix.as_deref())
+        .with_null_values(null_values);
+    let mut df = CsvReadOptions::default()
+        .with_has_header(opts.header)
+        .with_skip_rows(opts.skip_rows)
+        .with_parse_options(parse_options)
+        .into_reader_with_file_handle(Cursor::new(data))
+        .finish()?;
+
+    if opts.column_names.len() > df.width() {
+        return Err(PolarsError::ShapeMismatch(
+            format!(
+                "COLUMN_NAMES has {} names but the data has {} columns",
+                opts.column_names.len(),
+                df.width()
+            )
+            .into(),
+        ));
+    }
+    if !opts.column_names.is_empty() {
+        let names = opts
+            .column_names
+            .iter()
+            .map(|name| name.as_str().into())
+            .chain(
+                df.get_column_names_owned()
+                    .into_iter()
+                    .skip(opts.column_names.len()),
+            )
+            .collect::<Vec<PlSmallStr>>();
+        df.set_column_names(names)?;
+    }
+    Ok(df)
+}
+
+/// Infers the dialect from the first [`SNIFF_SAMPLE`] bytes of `data`: the
+/// line terminator from the first line break, the quote from the fields it
+/// opens, the delimiter splitting the records into the most consistent
+/// number of fields, and the header from columns whose first value does not
+/// look like the others.
+pub fn sniff(data: &[u8]) -> CsvDialect {
+    let truncated = data.len() > SNIFF_SAMPLE;
+    let text = String::from_utf8_lossy(&data[..data.len().min(SNIFF_SAMPLE)]);
+
+    let line_terminator = match text.find(['\r', '\n']).map(|i| &text[i..]) {
+        Some(rest) if rest.starts_with("\r\n") => "\r\n",
+        Some(rest) if rest.starts_with('\r') => "\r",
+        _ => "\n",
+    };
+    let quote = match quote_starts(&text, '\'') > quote_starts(&text, '"') {
+        true => '\'',
+        false => '"',
+    };
+
+    let mut best: Option<(f64, usize, char, Vec<Vec<String>>)> = None;
+    for delimiter in DELIMITERS {
+        let mut records = split_records(&text, delimiter, quote);
+        // A cut sample ends in a partial record.
+        if truncated && records.len() > 1 {
+            records.pop();
+        }
+        let mut counts = HashMap::new();
+        for record in &records {
+            *counts.entry(record.len()).or_insert(0usize) += 1;
+        }
+        let Some((fields, n)) = counts.into_iter().max_by_key(|&(fields, n)| (n, fields)) else {
+            continue;
+        };
+        if fields < 2 {
+            continue;
+        }
+        let consistency = n as f64 / records.len() as f64;
+        if best
+            .as_ref()
+            .is_none_or(|b| (consistency, fields) > (b.0, b.1))
+        {
+            best = Some((consistency, fields, delimiter, records));
+        }
+    }
+
+    let (delimiter, records) = best.map_or((',', Vec::new()), |b| (b.2, b.3));
+    CsvDialect {
+        delimiter,
+        quote: Some(quote),
+        header: has_header(&records),
+        line_terminator: line_terminator.to_string(),
+    }
+}
+
+/// Number of fields opened by `quote`, at the start of a line or after one
+/// of the candidate delimiters.
+fn quote_starts(text: &str, quote: char) -> usize {
+    let mut previous = '\n';
+    let mut count = 0;
+    for c in text.chars() {
+        if c == quote && (previous == '\n' || previous == '\r' || DELIMITERS.contains(&previous)) {
+            count += 1;
+        }
+        previous = c;
+    }
+    count
+}
+
+/// Splits `text` into records of fields, honouring quoted fields. Blank lines
+/// are dropped.
+fn split_records(text: &str, delimiter: char, quote: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c != quote {
+                field.push(c);
+            } else if chars.peek() == Some(&quote) {
+                field.push(quote);
+                chars.next();
+            } else {
+                in_quotes = false;
+            }
+        } else if c == quote && field.is_empty() {
+            in_quotes = true;
+        } else if c == delimiter {
+            record.push(mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            record.push(mem::take(&mut field));
+            records.push(mem::take(&mut record));
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|record| !(record.len() == 1 && record[0].is_empty()));
+    records
+}
+
+/// Votes column by column, as Python's `csv.Sniffer` does: a first value that
+/// is text above numbers, or of another length than values all of the same
+/// length, speaks for a header. Without any evidence, a first row of distinct
+/// non-empty values is taken as the header.
+fn has_header(records: &[Vec<String>]) -> bool {
+    let Some((first, rest)) = records.split_first() else {
+        return true;
+    };
+    let is_number = |v: &str| v.parse::<f64>().is_ok();
+
+    let mut votes = 0;
+    for (i, name) in first.iter().enumerate() {
+        let name = name.trim();
+        let values = rest
+            .iter()
+            .filter_map(|record| record.get(i))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            continue;
+        }
+        if values.iter().all(|v| is_number(v)) {
+            votes += if is_number(name) { -1 } else { 1 };
+        } else if values.iter().all(|v| v.len() == values[0].len()) {
+            votes += if name.len() == values[0].len() { -1 } else { 1 };
+        }
+    }
+
+    let mut names = first.iter().map(|name| name.trim()).collect::<Vec<_>>();
+    names.sort_unstable();
+    let distinct = names.windows(2).all(|w| w[0] != w[1]) && !names.contains(&"");
+    votes > 0 || (votes == 0 && distinct)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_csv_opts() {
+        assert_eq!(get_csv_opts(&HashMap::new()).unwrap(), CsvOpts::default());
+
+        let opts = get_csv_opts(&named(&[
+            ("DELIMITER", "\\t"),
+            ("QUOTE", "none"),
+            ("HEADER", "false"),
+            ("SKIP_ROWS", "2"),
+            ("COMMENT_PREFIX", "#"),
+            ("NULL_VALUES", "NA, -"),
+            ("COLUMN_NAMES", "a,b"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            opts,
+            CsvOpts {
+                delimiter: b'\t',
+                quote: None,
+                header: false,
+                skip_rows: 2,
+                comment_prefix: Some("#".to_string()),
+                null_values: vec!["NA".to_string(), "-".to_string()],
+                column_names: vec!["a".to_string(), "b".to_string()],
+                ..CsvOpts::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_sniff_opts() {
+        let opts = get_csv_opts(&named(&[("SNIFF", "true"), ("HEADER", "false")])).unwrap();
+        assert_eq!(
+            opts.sniff,
+            [Sniffed::DELIMITER, Sniffed::QUOTE, Sniffed::LINE_TERMINATOR]
+        );
+        assert!(opts.needs_sniffing());
+
+        let dialect = CsvDialect {
+            delimiter: ';',
+            quote: Some('\''),
+            header: true,
+            line_terminator: "\r".to_string(),
+        };
+        let opts = opts.with_dialect(dialect.clone());
+        assert!(!opts.needs_sniffing());
+        assert_eq!(
+            (opts.delimiter, opts.quote, opts.header, opts.eol),
+            (b';', Some(b'\''), false, b'\r')
+        );
+        assert_eq!(
+            CsvDialect::deserialize(&dialect.serialize().unwrap()).unwrap(),
+            dialect
+        );
+    }
+
+    #[test]
+    fn test_sniff() {
+        let data =
+            b"name;pop;note\r\n\"Chicago; IL\";2705627;\"big\"\r\nDallas;1219399;\"x;y\"\r\n";
+        assert_eq!(
+            sniff(data),
+            CsvDialect {
+                delimiter: ';',
+                quote: Some('"'),
+                header: true,
+                line_terminator: "\r\n".to_string(),
+            }
+        );
+
+        let dialect = sniff(b"1\t'a|b'\t2.5\n2\t'c'\t3\n");
+        assert_eq!(dialect.delimiter, '\t');
+        assert_eq!(dialect.quote, Some('\''));
+        assert!(!dialect.header);
+
+        let dialect = sniff(b"code|city\rIL|Chicago\rTX|Dallas\r");
+        assert_eq!(dialect.delimiter, '|');
+        assert_eq!(dialect.line_terminator, "\r");
+        assert!(dialect.header);
+    }
+
+    #[test]
+    fn test_sniff_truncated() {
+        let mut data = b"id,name\n".to_vec();
+        for i in 0..10_000 {
+            data.extend_from_slice(format!("{},city {}\n", i, i).as_bytes());
+        }
+        assert!(data.len() > SNIFF_SAMPLE);
+        let dialect = sniff(&data);
+        assert_eq!(dialect.delimiter, ',');
+        assert!(dialect.header);
+    }
+
+    #[test]
+    fn test_get_csv_opts_errors() {
+        assert!(get_csv_opts(&named(&[("DELIMITER", ";;")])).is_err());
+        assert!(get_csv_opts(&named(&[("QUOTE", "ab")])).is_err());
+        assert!(get_csv_opts(&named(&[("HEADER", "maybe")])).is_err());
+        assert!(get_csv_opts(&named(&[("SKIP_ROWS", "-1")])).is_err());
+    }
+
+    #[test]
+    fn test_read_csv_dialect() {
+        let data = b"exported 2025-01-01\n# comment\nname;pop\n'Chicago; IL';2705627\nDallas;NA\n";
+        let opts = CsvOpts {
+            delimiter: b';',
+            quote: Some(b'\''),
+            skip_rows: 1,
+            comment_prefix: Some("#".to_string()),
+            null_values: vec!["NA".to_string()],
+            ..CsvOpts::default()
+        };
+        let df = read_csv(data.to_vec(), &opts).unwrap();
+        assert_eq!(df.get_column_names(), ["name", "pop"]);
+        assert_eq!(
+            df.column("name").unwrap().str().unwrap().get(0),
+            Some("Chicago; IL")
+        );
+        assert_eq!(df.column("pop").unwrap().null_count(), 1);
+    }
+
+    #[test]
+    fn test_read_csv_column_names() {
+        let data = b"1,Chicago,IL\n2,Dallas,TX\n";
+        let opts = CsvOpts {
+            header: false,
+            column_names: vec!["id".to_string(), "city".to_string()],
+            ..CsvOpts::default()
+        };
+        let df = read_csv(data.to_vec(), &opts).unwrap();
+        assert_eq!(df.height(), 2);
+        assert_eq!(df.get_column_names(), ["id", "city", "column_3"]);
+
+        let opts = CsvOpts {
+            column_names: vec!["a", "b", "c", "d"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            ..opts
+        };
+        assert!(read_csv(data.to_vec(), &opts).is_err());
+    }
+}
@@ -1,12 +1,25 @@
 use std::error::Error;
 
-#[derive(Debug, PartialEq)]
+use crate::spreadsheet::detect_spreadsheet;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VTabDataFormats {
     CSV,
     AVRO,
     PARQUET,
     JSON,
     JSONL,
+    XLSX,
+    XLS,
+    ODS,
+    /// Arrow IPC file.
+    ARROW,
+    /// Feather v2, an Arrow IPC file under another name.
+    FEATHER,
+    /// Arrow IPC stream.
+    #[allow(non_camel_case_types)]
+    IPC_STREAM,
 }
 
 impl VTabDataFormats {
@@ -17,6 +30,12 @@ impl VTabDataFormats {
             VTabDataFormats::PARQUET => "PARQUET",
             VTabDataFormats::JSON => "JSON",
             VTabDataFormats::JSONL => "JSONL",
+            VTabDataFormats::XLSX => "XLSX",
+            VTabDataFormats::XLS => "XLS",
+            VTabDataFormats::ODS => "ODS",
+            VTabDataFormats::ARROW => "ARROW",
+            VTabDataFormats::FEATHER => "FEATHER",
+            VTabDataFormats::IPC_STREAM => "IPC_STREAM",
         }
     }
 }
@@ -29,10 +48,119 @@ pub fn get_format(fmt: &str) -> Result<VTabDataFormats, Box<dyn Error>> {
         "JSON" => Ok(VTabDataFormats::JSON),
         "JSONL" => Ok(VTabDataFormats::JSONL),
         "NDJSON" => Ok(VTabDataFormats::JSONL),
+        "XLSX" | "XLSM" => Ok(VTabDataFormats::XLSX),
+        "XLS" => Ok(VTabDataFormats::XLS),
+        "ODS" => Ok(VTabDataFormats::ODS),
+        "ARROW" | "IPC" => Ok(VTabDataFormats::ARROW),
+        "FEATHER" => Ok(VTabDataFormats::FEATHER),
+        "IPC_STREAM" | "ARROW_STREAM" => Ok(VTabDataFormats::IPC_STREAM),
         _ => Err(format!("Unknown data format: {}", fmt).into()),
     }
 }
 
+fn from_extension(file_name: &str) -> Option<VTabDataFormats> {
+    let (_, ext) = file_name.rsplit_once('.')?;
+    match ext.to_lowercase().as_str() {
+        "csv" => Some(VTabDataFormats::CSV),
+        "avro" => Some(VTabDataFormats::AVRO),
+        "parquet" | "pq" => Some(VTabDataFormats::PARQUET),
+        "json" => Some(VTabDataFormats::JSON),
+        "jsonl" | "ndjson" => Some(VTabDataFormats::JSONL),
+        "xlsx" | "xlsm" => Some(VTabDataFormats::XLSX),
+        "xls" => Some(VTabDataFormats::XLS),
+        "ods" => Some(VTabDataFormats::ODS),
+        "arrow" | "ipc" => Some(VTabDataFormats::ARROW),
+        "feather" => Some(VTabDataFormats::FEATHER),
+        "arrows" => Some(VTabDataFormats::IPC_STREAM),
+        _ => None,
+    }
+}
+
+fn from_content_type(content_type: &str) -> Option<VTabDataFormats> {
+    let mime = content_type.split(';').next()?.trim().to_lowercase();
+    match mime.as_str() {
+        "text/csv" | "application/csv" => Some(VTabDataFormats::CSV),
+        "avro/binary" | "application/avro" | "application/vnd.apache.avro+binary" => {
+            Some(VTabDataFormats::AVRO)
+        }
+        "application/vnd.apache.parquet" | "application/x-parquet" => {
+            Some(VTabDataFormats::PARQUET)
+        }
+        "application/json" => Some(VTabDataFormats::JSON),
+        "application/x-ndjson" | "application/jsonl" | "application/x-jsonlines" => {
+            Some(VTabDataFormats::JSONL)
+        }
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+            Some(VTabDataFormats::XLSX)
+        }
+        "application/vnd.ms-excel" => Some(VTabDataFormats::XLS),
+        "application/vnd.oasis.opendocument.spreadsheet" => Some(VTabDataFormats::ODS),
+        "application/vnd.apache.arrow.file" => Some(VTabDataFormats::ARROW),
+        "application/vnd.apache.arrow.stream" => Some(VTabDataFormats::IPC_STREAM),
+        _ => None,
+    }
+}
+
+fn from_magic(bytes: &[u8]) -> Option<VTabDataFormats> {
+    if bytes.starts_with(b"PAR1") {
+        return Some(VTabDataFormats::PARQUET);
+    }
+    if bytes.starts_with(b"Obj\x01") {
+        return Some(VTabDataFormats::AVRO);
+    }
+    if bytes.starts_with(b"ARROW1") {
+        return Some(VTabDataFormats::ARROW);
+    }
+    // Every message of a stream starts with a continuation marker.
+    if bytes.starts_with(b"\xff\xff\xff\xff") {
+        return Some(VTabDataFormats::IPC_STREAM);
+    }
+    if let Some(format) = detect_spreadsheet(bytes) {
+        return Some(format);
+    }
+
+    let sample = &bytes[..bytes.len().min(64 * 1024)];
+    let text = String::from_utf8_lossy(sample.strip_prefix(b"\xef\xbb\xbf").unwrap_or(sample));
+    let text = text.trim_start();
+    if text.starts_with('[') {
+        return Some(VTabDataFormats::JSON);
+    }
+    if text.starts_with('{') {
+        // One object per line is JSONL, anything else (e.g. pretty printed)
+        // is a single JSON document.
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .take(2)
+            .collect::<Vec<_>>();
+        let jsonl =
+            lines.len() == 2 && lines.iter().all(|l| l.starts_with('{') && l.ends_with('}'));
+        return Some(match jsonl {
+            true => VTabDataFormats::JSONL,
+            false => VTabDataFormats::JSON,
+        });
+    }
+    if text.lines().next().is_some_and(|l| l.contains(',')) {
+        return Some(VTabDataFormats::CSV);
+    }
+    None
+}
+
+/// Infers the format of a payload when `FORMAT` is omitted, from the file
+/// extension, then the `Content-Type`, then the leading bytes.
+pub fn infer_format(
+    file_name: Option<&str>,
+    content_type: Option<&str>,
+    bytes: &[u8],
+) -> Result<VTabDataFormats, Box<dyn Error>> {
+    file_name
+        .and_then(from_extension)
+        .or_else(|| content_type.and_then(from_content_type))
+        .or_else(|| from_magic(bytes))
+        .ok_or_else(|| "Could not infer the data format, set FORMAT".into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,6 +173,15 @@ mod tests {
         assert_eq!(get_format("JSON").unwrap(), VTabDataFormats::JSON);
         assert_eq!(get_format("jsonl").unwrap(), VTabDataFormats::JSONL);
         assert_eq!(get_format("NDJSON").unwrap(), VTabDataFormats::JSONL);
+        assert_eq!(get_format("xlsx").unwrap(), VTabDataFormats::XLSX);
+        assert_eq!(get_format("XLS").unwrap(), VTabDataFormats::XLS);
+        assert_eq!(get_format("ods").unwrap(), VTabDataFormats::ODS);
+        assert_eq!(get_format("arrow").unwrap(), VTabDataFormats::ARROW);
+        assert_eq!(get_format("Feather").unwrap(), VTabDataFormats::FEATHER);
+        assert_eq!(
+            get_format("IPC_STREAM").unwrap(),
+            VTabDataFormats::IPC_STREAM
+        );
     }
 
     #[test]
@@ -55,4 +192,55 @@ mod tests {
             assert_eq!(e.to_string(), "Unknown data format: xml");
         }
     }
+
+    #[test]
+    fn test_infer_format_extension() {
+        assert_eq!(
+            infer_format(Some("cities.csv"), Some("application/json"), b"").unwrap(),
+            VTabDataFormats::CSV
+        );
+        assert_eq!(
+            infer_format(Some("events.NDJSON"), None, b"").unwrap(),
+            VTabDataFormats::JSONL
+        );
+        assert_eq!(
+            infer_format(Some("budget.xlsx"), None, b"").unwrap(),
+            VTabDataFormats::XLSX
+        );
+    }
+
+    #[test]
+    fn test_infer_format_content_type() {
+        assert_eq!(
+            infer_format(Some("export"), Some("text/csv; charset=utf-8"), b"").unwrap(),
+            VTabDataFormats::CSV
+        );
+        assert_eq!(
+            infer_format(None, Some("application/x-ndjson"), b"").unwrap(),
+            VTabDataFormats::JSONL
+        );
+    }
+
+    #[test]
+    fn test_infer_format_magic() {
+        let infer = |bytes: &[u8]| infer_format(None, Some("application/octet-stream"), bytes);
+        assert_eq!(infer(b"PAR1\x15\x04").unwrap(), VTabDataFormats::PARQUET);
+        assert_eq!(infer(b"Obj\x01\x04\x14").unwrap(), VTabDataFormats::AVRO);
+        assert_eq!(infer(b"ARROW1\x00\x00").unwrap(), VTabDataFormats::ARROW);
+        assert_eq!(
+            infer(b"\xff\xff\xff\xff\x78\x00").unwrap(),
+            VTabDataFormats::IPC_STREAM
+        );
+        assert_eq!(infer(b"  [{\"a\": 1}]").unwrap(), VTabDataFormats::JSON);
+        assert_eq!(infer(b"{\n  \"a\": 1\n}\n").unwrap(), VTabDataFormats::JSON);
+        assert_eq!(
+            infer(b"{\"a\": 1}\n{\"a\": 2}\n").unwrap(),
+            VTabDataFormats::JSONL
+        );
+        assert_eq!(infer(b"a,b\n1,2\n").unwrap(), VTabDataFormats::CSV);
+        assert_eq!(
+            infer(b"\x00\x01\x02").unwrap_err().to_string(),
+            "Could not infer the data format, set FORMAT"
+        );
+    }
 }
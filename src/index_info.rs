@@ -0,0 +1,71 @@
+//! Parts of `sqlite3_index_info` that sqlite-loadable's `IndexInfo` does not
+//! expose.
+
+use std::{
+    ffi::CStr,
+    mem, ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use sqlite_loadable::{
+    api::{self, ValueType},
+    ext::{sqlite3_api_routines, sqlite3_index_info},
+    table::IndexInfo,
+    SQLITE_OKAY,
+};
+
+/// The SQLite API handed to the extension entrypoint.
+static API: AtomicPtr<sqlite3_api_routines> = AtomicPtr::new(ptr::null_mut());
+
+pub fn set_api(api: *mut sqlite3_api_routines) {
+    if !api.is_null() {
+        API.store(api, Ordering::Relaxed);
+    }
+}
+
+/// `IndexInfo` only wraps the pointer SQLite passes to xBestIndex. This is
+/// private to sqlite-loadable, hence its exact version in Cargo.toml.
+fn raw(info: &IndexInfo) -> *mut sqlite3_index_info {
+    const _: () = assert!(mem::size_of::<IndexInfo>() == mem::size_of::<*mut sqlite3_index_info>());
+    unsafe { *(info as *const IndexInfo as *const *mut sqlite3_index_info) }
+}
+
+pub fn set_order_by_consumed(info: &IndexInfo) {
+    unsafe { (*raw(info)).orderByConsumed = 1 }
+}
+
+/// Name of the collating sequence of the `i`-th constraint, see
+/// `sqlite3_vtab_collation`.
+pub fn collation(info: &IndexInfo, i: usize) -> String {
+    let api = API.load(Ordering::Relaxed);
+    let name = unsafe {
+        match api.as_ref().and_then(|api| api.vtab_collation) {
+            Some(vtab_collation) => vtab_collation(raw(info), i as i32),
+            None => ptr::null(),
+        }
+    };
+    match name.is_null() {
+        true => "BINARY".to_string(),
+        false => unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .to_uppercase(),
+    }
+}
+
+/// Right-hand value of the `i`-th constraint when it is a number already
+/// known while planning, see `sqlite3_vtab_rhs_value`.
+pub fn rhs_number(info: &IndexInfo, i: usize) -> Option<f64> {
+    let api = API.load(Ordering::Relaxed);
+    let mut value = ptr::null_mut();
+    let rc = unsafe {
+        let vtab_rhs_value = api.as_ref()?.vtab_rhs_value?;
+        vtab_rhs_value(raw(info), i as i32, &mut value)
+    };
+    if rc != SQLITE_OKAY || value.is_null() {
+        return None;
+    }
+    match api::value_type(&value) {
+        ValueType::Integer | ValueType::Float => Some(api::value_double(&value)),
+        _ => None,
+    }
+}
@@ -1,30 +1,107 @@
+mod archive;
 mod args;
 mod avro;
+mod compression;
+mod csv;
+mod fetch;
 mod fmt;
+mod index_info;
+mod parquet_range;
+mod pattern;
+mod plan;
+mod query;
+mod record_path;
+mod refresh;
+mod sources;
+mod spreadsheet;
+mod stats;
 mod storage;
 
-use args::parse_args;
+use archive::{detect_archive, extract_member};
+use args::{parse_args, parse_bool};
 use avro::AvroReader;
-use chrono::{DateTime, NaiveDate};
-use fmt::{get_format, VTabDataFormats};
+use chrono::{DateTime, NaiveDate, Utc};
+use compression::{
+    decompress, decompress_detected, get_compression, strip_compression_extension, Compression,
+};
+use csv::{get_csv_opts, read_csv, sniff, CsvDialect, CsvOpts};
+use fetch::{
+    fetch, fetch_if_modified, get_fetch_opts, DataSource, FetchOpts, FetchResponse, Validators,
+};
+use fmt::{get_format, infer_format, VTabDataFormats};
+use parquet_range::{scan_local, ParquetRangeReader, RangeReader, RowGroupCache, RowGroupFilter};
+use pattern::{glob_to_regex, like_to_regex};
+use plan::{OrderTerm, PlanConstraint, PlanOp, QueryPlan};
 use polars::prelude::*;
-use reqwest::blocking::get;
+use record_path::{parse_record_path, RecordPath};
+use regex::Regex;
+use sources::{expand_sources, redact_sources};
+use spreadsheet::{detect_spreadsheet, parse_range, CellRange, SpreadsheetReader};
 use sqlite_loadable::{
-    api, define_virtual_table,
-    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    api, define_scalar_function, define_table_function, define_virtual_table,
+    table::{
+        BestIndexError, ConstraintOperator, InValues, IndexInfo, OrderByDirection, VTab,
+        VTabArguments, VTabCursor,
+    },
     Result,
 };
-use sqlite_loadable::{prelude::*, Error};
-use std::{mem, os::raw::c_int};
+use sqlite_loadable::{prelude::*, Error, FunctionFlags};
+use stats::TableStats;
+use std::{
+    collections::HashMap,
+    mem,
+    os::raw::c_int,
+    sync::{Arc, Mutex, RwLock},
+};
 
 use storage::{df_dtype_to_sqlite_dtype, get_storage, Statement, StorageOpts};
 
 #[repr(C)]
 struct UrlTable {
     base: sqlite3_vtab,
-    df: DataFrame,
+    /// Connection of the table, asked while planning which `like()` and
+    /// `glob()` functions are registered.
+    db: *mut sqlite3,
+    /// Shared with `httpfs_refresh`, which swaps in new data.
+    df: Arc<RwLock<DataFrame>>,
+    /// Computed on first use, cleared by `httpfs_refresh`.
+    stats: Arc<Mutex<Option<TableStats>>>,
     headers: Vec<String>,
     columns_types: Vec<String>,
+    remote: Option<RangeReader>,
+}
+
+/// Options of a table, parsed from its `CREATE VIRTUAL TABLE` arguments.
+struct TableOpts {
+    url: String,
+    format: Option<VTabDataFormats>,
+    storage: StorageOpts,
+    fetch_opts: FetchOpts,
+    compression: Option<Compression>,
+    member: Option<String>,
+    csv_opts: CsvOpts,
+    record_path: Option<RecordPath>,
+    sheet: Option<String>,
+    range: Option<CellRange>,
+    source_column: bool,
+    refresh: bool,
+}
+
+/// Cache validators and size of one fetched source, kept in `_metadata`.
+struct SourceInfo {
+    source: String,
+    validators: Validators,
+    content_length: Option<u64>,
+}
+
+/// Data fetched from the sources of a table.
+struct Loaded {
+    df: DataFrame,
+    format: Option<VTabDataFormats>,
+    sources: Vec<SourceInfo>,
+    fetched_at: String,
+    /// Sniffed dialect of CSV data, with `SNIFF`.
+    dialect: Option<CsvDialect>,
 }
 
 impl UrlTable {
@@ -34,9 +111,126 @@ impl UrlTable {
         vt_args: VTabArguments,
         is_created: bool,
     ) -> Result<(String, Self)> {
-        let args = vt_args.arguments;
-        if args.len() < 2 {
-            return Err(Error::new_message("URL and FORMAT args must be provided"));
+        let mut opts = Self::parse_opts(vt_args.arguments)?;
+        let (module_name, table_name) = (&vt_args.module_name, &vt_args.table_name);
+
+        let t_name = format!("\"{}.{}_metadata\"", module_name, table_name);
+        let fetch_data = is_created && !Self::has_metadata(db, &t_name)?;
+        // Reloads parse the data with the dialect sniffed when the table was
+        // created, not with whatever the new data looks like.
+        if opts.csv_opts.needs_sniffing() && !fetch_data {
+            if let Some(dialect) = Self::read_dialect(db, module_name, table_name) {
+                opts.csv_opts = opts.csv_opts.with_dialect(dialect);
+            }
+        }
+        let remote = if opts.storage == StorageOpts::REMOTE {
+            let mut sources = expand_sources(&opts.url, &opts.fetch_opts)
+                .map_err(|e| Error::new_message(format!("{}", e)))?;
+            if sources.len() != 1 {
+                return Err(Error::new_message(
+                    "REMOTE storage only supports a single source",
+                ));
+            }
+            let reader = RangeReader::new(sources.remove(0), opts.fetch_opts.clone())
+                .map_err(|e| Error::new_message(format!("{}", e)))?;
+            Some(reader)
+        } else {
+            None
+        };
+
+        let df = if let Some(reader) = &remote {
+            ParquetRangeReader::new(reader.clone())
+                .schema()
+                .map(|schema| DataFrame::empty_with_schema(&schema))
+                .map_err(|e| Error::new_message(format!("Parquet parse error: {}", e)))?
+        } else if fetch_data {
+            let loaded = Self::load(&opts, &HashMap::new())?
+                .ok_or_else(|| Error::new_message("Internal bug: nothing fetched"))?;
+            // Runs inside the CREATE statement, which rolls everything back
+            // on error. A savepoint could not be released before it ends.
+            if opts.storage == StorageOpts::SQLITE {
+                Self::replace_shadow_tables(db, module_name, table_name, &opts, &loaded)?;
+            }
+            if let Some(dialect) = loaded.dialect {
+                opts.csv_opts = opts.csv_opts.with_dialect(dialect);
+            }
+            loaded.df
+        } else if opts.refresh && opts.storage == StorageOpts::SQLITE {
+            let validators = Self::read_validators(db, module_name, table_name);
+            match Self::load(&opts, &validators)? {
+                // The database may be read-only, or busy with the statement
+                // being prepared. The new data is still served, the shadow
+                // tables are left for httpfs_refresh to rewrite.
+                Some(loaded) => {
+                    let _ = Self::write_shadow_tables(db, module_name, table_name, &opts, &loaded);
+                    loaded.df
+                }
+                None => Self::read_shadow_data(db, module_name, table_name)?,
+            }
+        } else {
+            Self::read_shadow_data(db, module_name, table_name)?
+        };
+
+        let headers = df
+            .get_column_names_owned()
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+
+        let columns_types = Self::columns_types(&df);
+        let columns_def = Self::columns_def(&df);
+
+        let stats = match &remote {
+            Some(reader) => Some(
+                ParquetRangeReader::new(reader.clone())
+                    .stats()
+                    .map_err(|e| Error::new_message(format!("Parquet parse error: {}", e)))?,
+            ),
+            None => None,
+        };
+
+        let df = Arc::new(RwLock::new(df));
+        let stats = Arc::new(Mutex::new(stats));
+        refresh::register(db, module_name, table_name, Arc::new(opts), &df, &stats);
+
+        let schema = format!("CREATE TABLE x({});", columns_def);
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            schema,
+            UrlTable {
+                base,
+                db,
+                df,
+                stats,
+                headers,
+                columns_types,
+                remote,
+            },
+        ))
+    }
+
+    /// The current data of the table. Cheap, columns are reference counted.
+    fn snapshot(&self) -> Result<DataFrame> {
+        self.df
+            .read()
+            .map(|df| df.clone())
+            .map_err(|_| Error::new_message("Table lock poisoned"))
+    }
+
+    fn stats(&self) -> Result<TableStats> {
+        let mut stats = self
+            .stats
+            .lock()
+            .map_err(|_| Error::new_message("Table lock poisoned"))?;
+        if stats.is_none() {
+            *stats = Some(TableStats::from_dataframe(&self.snapshot()?));
+        }
+        Ok(stats.clone().unwrap_or_default())
+    }
+
+    fn parse_opts(args: Vec<String>) -> Result<TableOpts> {
+        if args.is_empty() {
+            return Err(Error::new_message("URL arg must be provided"));
         }
 
         let parsed_args = parse_args(args);
@@ -44,14 +238,16 @@ impl UrlTable {
             .named
             .get("URL")
             .or_else(|| parsed_args.positional.get(0))
-            .ok_or_else(|| Error::new_message("No URL provided"))?;
+            .ok_or_else(|| Error::new_message("No URL provided"))?
+            .to_string();
 
         let format = parsed_args
             .named
             .get("FORMAT")
             .or_else(|| parsed_args.positional.get(1))
-            .ok_or_else(|| Error::new_message("No data format specified"))
-            .and_then(|f| get_format(&f).map_err(|err| Error::new_message(format!("{}", err))))?;
+            .map(|f| get_format(f))
+            .transpose()
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
 
         let storage = parsed_args
             .named
@@ -62,163 +258,468 @@ impl UrlTable {
                 |opt| get_storage(opt).map_err(|err| Error::new_message(format!("{}", err))),
             )?;
 
-        let t_name = format!(
-            "\"{}.{}_metadata\"",
-            vt_args.module_name, vt_args.table_name
-        );
-        let fetch_data = is_created && !Self::has_metadata(db, &t_name)?;
-        let df = if fetch_data {
-            let resp = get(url)
-                .map_err(|e| Error::new_message(&format!("HTTP error: {}", e)))?
-                .bytes()
-                .map_err(|e| Error::new_message(&format!("Read error: {}", e)))?;
-
-            match format {
-                VTabDataFormats::CSV => CsvReader::new(std::io::Cursor::new(resp))
-                    .finish()
-                    .map_err(|e| Error::new_message(&format!("CSV parse error: {}", e)))?,
-                VTabDataFormats::PARQUET => ParquetReader::new(std::io::Cursor::new(resp))
-                    .finish()
-                    .map_err(|e| Error::new_message(&format!("Parquet parse error: {}", e)))?,
-                VTabDataFormats::AVRO => AvroReader::new(resp.as_ref())
-                    .finish()
-                    .map_err(|e| Error::new_message(&format!("Avro build error: {}", e)))?,
-                VTabDataFormats::JSON => JsonReader::new(std::io::Cursor::new(resp))
-                    .with_json_format(JsonFormat::Json)
-                    .finish()
-                    .map_err(|e| Error::new_message(&format!("JSON build error: {}", e)))?,
-                VTabDataFormats::JSONL => JsonReader::new(std::io::Cursor::new(resp))
-                    .with_json_format(JsonFormat::JsonLines)
-                    .finish()
-                    .map_err(|e| Error::new_message(&format!("JSON build error: {}", e)))?,
-            }
-        } else {
-            let metadata_sql = format!(
-                "SELECT HEADERS FROM \"{}.{}_metadata\";",
-                vt_args.module_name, vt_args.table_name
-            );
-            let stmt = Statement::build(db, &metadata_sql)
-                .map_err(|e| Error::new_message(e.to_string()))?;
-            let results = stmt
-                .fetch(1)
-                .map_err(|e| Error::new_message(e.to_string()))?;
-            let raw_headers = results.get(0).and_then(|row| row.get(0));
-            let headers: Vec<&str> = match raw_headers {
-                Some(h) => Self::split_csv_line(h),
-                None => todo!("Internal bug"),
+        if storage == StorageOpts::REMOTE && format.is_some_and(|f| f != VTabDataFormats::PARQUET) {
+            return Err(Error::new_message(
+                "REMOTE storage is only supported for PARQUET",
+            ));
+        }
+
+        let fetch_opts = get_fetch_opts(&parsed_args.named)
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let compression = parsed_args
+            .named
+            .get("COMPRESSION")
+            .map_or(Ok(None), |opt| get_compression(opt))
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let member = parsed_args
+            .named
+            .get("MEMBER")
+            .or_else(|| parsed_args.named.get("ARCHIVE_PATH"))
+            .cloned();
+
+        let csv_opts = get_csv_opts(&parsed_args.named)
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let record_path = parsed_args
+            .named
+            .get("RECORD_PATH")
+            .map(|path| parse_record_path(path))
+            .transpose()
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let sheet = parsed_args.named.get("SHEET").cloned();
+        let range = parsed_args
+            .named
+            .get("RANGE")
+            .map(|range| parse_range(range))
+            .transpose()
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let source_column = parsed_args
+            .named
+            .get("SOURCE_COLUMN")
+            .map_or(Ok(false), |v| parse_bool(v))
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        let refresh = parsed_args
+            .named
+            .get("REFRESH")
+            .map_or(Ok(false), |v| parse_bool(v))
+            .map_err(|err| Error::new_message(format!("{}", err)))?;
+
+        Ok(TableOpts {
+            url,
+            format,
+            storage,
+            fetch_opts,
+            compression,
+            member,
+            csv_opts,
+            record_path,
+            sheet,
+            range,
+            source_column,
+            refresh,
+        })
+    }
+
+    /// Fetches and parses every source of the table. With `validators` from a
+    /// previous fetch, returns `None` when no source changed since then.
+    fn load(opts: &TableOpts, validators: &HashMap<String, Validators>) -> Result<Option<Loaded>> {
+        let sources = expand_sources(&opts.url, &opts.fetch_opts)
+            .map_err(|e| Error::new_message(format!("{}", e)))?;
+
+        let no_validators = Validators::default();
+        let mut responses = Vec::with_capacity(sources.len());
+        for source in &sources {
+            let validators = validators
+                .get(&source.to_string())
+                .unwrap_or(&no_validators);
+            let resp = fetch_if_modified(source, &opts.fetch_opts, validators)
+                .map_err(|e| Error::new_message(format!("{}", e)))?;
+            responses.push(resp);
+        }
+        if !validators.is_empty() && responses.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+
+        let fetched_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let mut csv_opts = opts.csv_opts.clone();
+        let mut detected_format = None;
+        let mut infos = Vec::with_capacity(sources.len());
+        let mut frames = Vec::with_capacity(sources.len());
+        for (source, resp) in sources.iter().zip(responses) {
+            // Unchanged sources of a changed table are downloaded again.
+            let resp = match resp {
+                Some(resp) => resp,
+                None => fetch(source, &opts.fetch_opts)
+                    .map_err(|e| Error::new_message(format!("{}", e)))?,
             };
+            infos.push(SourceInfo {
+                source: source.to_string(),
+                validators: resp.validators(),
+                content_length: resp
+                    .header("Content-Length")
+                    .and_then(|v| v.parse().ok())
+                    .or(Some(resp.body.len() as u64)),
+            });
+
+            let content_type = resp.header("Content-Type").map(str::to_string);
+            let (name, resp) =
+                Self::unpack(source, resp, opts.compression, opts.member.as_deref())?;
+            let source_format = match opts.format {
+                Some(format) => format,
+                None => {
+                    let inferred = infer_format(name.as_deref(), content_type.as_deref(), &resp)
+                        .map_err(|e| Error::new_message(format!("{}: {}", source, e)))?;
+                    detected_format.get_or_insert(inferred);
+                    inferred
+                }
+            };
+            // The first CSV source sets the dialect of all of them.
+            if source_format == VTabDataFormats::CSV && csv_opts.needs_sniffing() {
+                csv_opts = csv_opts.with_dialect(sniff(&resp));
+            }
+            let mut df =
+                Self::read_dataframe(&source_format, resp, opts, &csv_opts).map_err(|e| {
+                    if sources.len() > 1 {
+                        Error::new_message(format!("{}: {}", source, e))
+                    } else {
+                        e
+                    }
+                })?;
+            if opts.source_column {
+                let values = vec![source.to_string(); df.height()];
+                df.with_column(Column::new("_source".into(), values))
+                    .map_err(|e| Error::new_message(e.to_string()))?;
+            }
+            frames.push(df.lazy());
+        }
 
-            let data_sql = format!(
-                "SELECT * FROM  \"{}.{}_data\";",
-                vt_args.module_name, vt_args.table_name
-            );
-            let stmt =
-                Statement::build(db, &data_sql).map_err(|e| Error::new_message(e.to_string()))?;
-            let results = stmt
-                .fetch(headers.len().try_into().unwrap())
-                .map_err(|e| Error::new_message(e.to_string()))?;
-
-            Self::dataframe_from_rows(results, Some(headers))
-                .map_err(|e| Error::new_message(e.to_string()))?
+        let args = UnionArgs {
+            to_supertypes: true,
+            ..Default::default()
         };
+        let df = concat_lf_diagonal(frames, args)
+            .map(|lf| match opts.source_column {
+                true => lf.select([all().exclude(["_source"]), col("_source")]),
+                false => lf,
+            })
+            .and_then(|lf| lf.collect())
+            .map_err(|e| Error::new_message(format!("Concat error: {}", e)))?;
+
+        Ok(Some(Loaded {
+            df,
+            format: opts.format.or(detected_format),
+            sources: infos,
+            fetched_at,
+            dialect: csv_opts.dialect,
+        }))
+    }
 
-        let headers = df
-            .get_column_names_owned()
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-
-        let columns_types = df
-            .dtypes()
+    fn columns_types(df: &DataFrame) -> Vec<String> {
+        df.dtypes()
             .into_iter()
             .map(|col_dtype| df_dtype_to_sqlite_dtype(&col_dtype).as_str().to_string())
-            .collect::<Vec<String>>();
+            .collect::<Vec<String>>()
+    }
 
-        let columns_def = df
-            .get_column_names()
+    fn columns_def(df: &DataFrame) -> String {
+        df.get_column_names()
             .iter()
-            .zip(columns_types.iter())
+            .zip(Self::columns_types(df).iter())
             .map(|(name, ty)| format!("\"{}\" {}", name, ty))
             .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn execute(db: *mut sqlite3, sql: &str) -> Result<()> {
+        Statement::build(db, sql)
+            .map_err(|e| Error::new_message(e.to_string()))?
+            .execute()
+            .map_err(|e| Error::new_message(e.to_string()))?
+            .finalize()
+            .map_err(|e| Error::new_message(e.to_string()))
+    }
+
+    /// (Re)writes the `_data` and `_metadata` shadow tables. Either both are
+    /// replaced or, on error, neither is.
+    fn write_shadow_tables(
+        db: *mut sqlite3,
+        module_name: &str,
+        table_name: &str,
+        opts: &TableOpts,
+        loaded: &Loaded,
+    ) -> Result<()> {
+        Self::execute(db, "SAVEPOINT httpfs_write;")?;
+        let result = Self::replace_shadow_tables(db, module_name, table_name, opts, loaded);
+        if result.is_err() {
+            let _ = Self::execute(db, "ROLLBACK TO httpfs_write;");
+        }
+        Self::execute(db, "RELEASE httpfs_write;")?;
+        result
+    }
+
+    fn replace_shadow_tables(
+        db: *mut sqlite3,
+        module_name: &str,
+        table_name: &str,
+        opts: &TableOpts,
+        loaded: &Loaded,
+    ) -> Result<()> {
+        let df = &loaded.df;
+        Self::execute(
+            db,
+            &format!(
+                "DROP TABLE IF EXISTS \"{}.{}_data\";",
+                module_name, table_name
+            ),
+        )?;
+        let data_schema = format!(
+            "CREATE TABLE \"{}.{}_data\" ({});",
+            module_name,
+            table_name,
+            Self::columns_def(df)
+        );
+        Self::execute(db, &data_schema)?;
+
+        let parsed_headers = df
+            .get_column_names()
+            .into_iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect::<Vec<_>>()
             .join(", ");
 
-        if storage == StorageOpts::SQLITE && is_created {
-            let data_schema = format!(
-                "CREATE TABLE \"{}.{}_data\" ({});",
-                vt_args.module_name, vt_args.table_name, columns_def
-            );
-            Statement::build(db, &data_schema)
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .execute()
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .finalize()
-                .map_err(|e| Error::new_message(e.to_string()))?;
-
-            let parsed_headers = headers
-                .clone()
-                .into_iter()
-                .map(|name| format!("\"{}\"", name))
-                .collect::<Vec<_>>()
-                .join(", ");
-
-            let batch_size = 1_000;
-            let data_data = Self::insert_dataframe_in_batches(
-                &df,
-                &vt_args.module_name,
-                &vt_args.table_name,
-                parsed_headers.clone(),
-                batch_size,
-            );
+        let batch_size = 1_000;
+        let data_data = Self::insert_dataframe_in_batches(
+            df,
+            module_name,
+            table_name,
+            parsed_headers.clone(),
+            batch_size,
+        );
 
-            for data in data_data {
-                Statement::build(db, &data)
-                    .map_err(|e| Error::new_message(e.to_string()))?
-                    .execute()
-                    .map_err(|e| Error::new_message(e.to_string()))?
-                    .finalize()
-                    .map_err(|e| Error::new_message(e.to_string()))?;
-            }
+        for data in data_data {
+            Self::execute(db, &data)?;
+        }
 
-            let metadata_schema = format!(
-                "CREATE TABLE \"{}.{}_metadata\" (URL TEXT, FORMAT TEXT, HEADERS TEXT, COLUMN_TYPES TEXT);",
-                vt_args.module_name, vt_args.table_name
-            );
-            Statement::build(db, &metadata_schema)
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .execute()
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .finalize()
-                .map_err(|e| Error::new_message(e.to_string()))?;
+        Self::execute(
+            db,
+            &format!(
+                "DROP TABLE IF EXISTS \"{}.{}_metadata\";",
+                module_name, table_name
+            ),
+        )?;
+        let metadata_schema = format!(
+            "CREATE TABLE \"{}.{}_metadata\" (URL TEXT, FORMAT TEXT, HEADERS TEXT, COLUMN_TYPES TEXT, SOURCE TEXT, ETAG TEXT, LAST_MODIFIED TEXT, CONTENT_LENGTH INTEGER, FETCHED_AT TEXT, DIALECT TEXT);",
+            module_name, table_name
+        );
+        Self::execute(db, &metadata_schema)?;
 
+        let dialect = loaded
+            .dialect
+            .as_ref()
+            .map(|dialect| dialect.serialize())
+            .transpose()
+            .map_err(|e| Error::new_message(e.to_string()))?;
+
+        // One row per source, the table level columns are repeated.
+        for info in &loaded.sources {
             let metadata_data = format!(
-                "INSERT INTO\"{}.{}_metadata\" (URL, FORMAT, HEADERS, COLUMN_TYPES) VALUES ('{}', '{}', '{}', '{}');",
-                vt_args.module_name,
-                vt_args.table_name,
-                url,
-                format.as_str(),
-                parsed_headers.clone(),
-                columns_types.join(", ")
+                "INSERT INTO \"{}.{}_metadata\" (URL, FORMAT, HEADERS, COLUMN_TYPES, SOURCE, ETAG, LAST_MODIFIED, CONTENT_LENGTH, FETCHED_AT, DIALECT) VALUES ('{}', '{}', '{}', '{}', '{}', {}, {}, {}, '{}', {});",
+                module_name,
+                table_name,
+                Self::escape_sql_string(&redact_sources(&opts.url)),
+                loaded.format.map_or("", |f| f.as_str()),
+                Self::escape_sql_string(&parsed_headers),
+                Self::columns_types(df).join(", "),
+                Self::escape_sql_string(&info.source),
+                Self::format_sql_value(info.validators.etag.as_deref().map_or(AnyValue::Null, AnyValue::String)),
+                Self::format_sql_value(
+                    info.validators
+                        .last_modified
+                        .as_deref()
+                        .map_or(AnyValue::Null, AnyValue::String)
+                ),
+                info.content_length
+                    .map_or("NULL".to_string(), |len| len.to_string()),
+                loaded.fetched_at,
+                Self::format_sql_value(dialect.as_deref().map_or(AnyValue::Null, AnyValue::String))
             );
+            Self::execute(db, &metadata_data)?;
+        }
+        Ok(())
+    }
+
+    /// Validators of the last fetch, by source. Tables created by older
+    /// versions have none, so they are always downloaded again.
+    fn read_validators(
+        db: *mut sqlite3,
+        module_name: &str,
+        table_name: &str,
+    ) -> HashMap<String, Validators> {
+        let sql = format!(
+            "SELECT SOURCE, ETAG, LAST_MODIFIED FROM \"{}.{}_metadata\";",
+            module_name, table_name
+        );
+        let rows = Statement::build(db, &sql)
+            .and_then(|stmt| stmt.fetch(3))
+            .unwrap_or_default();
+        let value = |v: &String| Some(v.clone()).filter(|v| v != "NULL");
+
+        rows.iter()
+            .map(|row| {
+                let validators = Validators {
+                    etag: value(&row[1]),
+                    last_modified: value(&row[2]),
+                };
+                (row[0].clone(), validators)
+            })
+            .collect()
+    }
+
+    /// Dialect sniffed when the table was created. Tables created by older
+    /// versions have none, their data is sniffed again.
+    fn read_dialect(db: *mut sqlite3, module_name: &str, table_name: &str) -> Option<CsvDialect> {
+        let sql = format!(
+            "SELECT DIALECT FROM \"{}.{}_metadata\" WHERE DIALECT IS NOT NULL LIMIT 1;",
+            module_name, table_name
+        );
+        let rows = Statement::build(db, &sql)
+            .and_then(|stmt| stmt.fetch(1))
+            .ok()?;
+        CsvDialect::deserialize(rows.first()?.first()?).ok()
+    }
+
+    fn read_shadow_data(
+        db: *mut sqlite3,
+        module_name: &str,
+        table_name: &str,
+    ) -> Result<DataFrame> {
+        let metadata_sql = format!(
+            "SELECT HEADERS FROM \"{}.{}_metadata\";",
+            module_name, table_name
+        );
+        let stmt =
+            Statement::build(db, &metadata_sql).map_err(|e| Error::new_message(e.to_string()))?;
+        let results = stmt
+            .fetch(1)
+            .map_err(|e| Error::new_message(e.to_string()))?;
+        let raw_headers = results.get(0).and_then(|row| row.get(0));
+        let headers: Vec<&str> = match raw_headers {
+            Some(h) => Self::split_csv_line(h),
+            None => {
+                return Err(Error::new_message(format!(
+                    "No stored headers for table {}, drop it and create it again",
+                    table_name
+                )))
+            }
+        };
 
-            Statement::build(db, &metadata_data)
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .execute()
-                .map_err(|e| Error::new_message(e.to_string()))?
-                .finalize()
-                .map_err(|e| Error::new_message(e.to_string()))?;
+        let data_sql = format!("SELECT * FROM  \"{}.{}_data\";", module_name, table_name);
+        let stmt =
+            Statement::build(db, &data_sql).map_err(|e| Error::new_message(e.to_string()))?;
+        let columns = i32::try_from(headers.len())
+            .map_err(|_| Error::new_message(format!("Too many columns: {}", headers.len())))?;
+        let results = stmt
+            .fetch(columns)
+            .map_err(|e| Error::new_message(e.to_string()))?;
+
+        Self::dataframe_from_rows(results, Some(headers))
+            .map_err(|e| Error::new_message(e.to_string()))
+    }
+
+    /// Turns a fetched body into the bytes of the data file: decompresses it
+    /// and, for zip and tar archives, extracts the selected member. Also
+    /// returns the file name of the data, without compression extension.
+    fn unpack(
+        source: &DataSource,
+        resp: FetchResponse,
+        compression: Option<Compression>,
+        member: Option<&str>,
+    ) -> Result<(Option<String>, Vec<u8>)> {
+        let err = |e: Box<dyn std::error::Error>| Error::new_message(format!("{}: {}", source, e));
+        let file_name = source.file_name();
+        let content_encoding = resp.header("Content-Encoding").map(str::to_string);
+        let body = match compression {
+            Some(compression) => decompress(compression, resp.body),
+            None => {
+                decompress_detected(file_name.as_deref(), content_encoding.as_deref(), resp.body)
+            }
         }
+        .map_err(err)?;
+
+        // XLSX and ODS files are zip archives too.
+        match detect_archive(&body).filter(|_| detect_spreadsheet(&body).is_none()) {
+            Some(archive) => {
+                let (name, data) = extract_member(archive, &body, member).map_err(err)?;
+                let data = decompress_detected(Some(&name), None, data).map_err(err)?;
+                Ok((Some(strip_compression_extension(&name).to_string()), data))
+            }
+            None if member.is_some() => Err(Error::new_message(format!(
+                "{}: MEMBER is set but the data is not a zip or tar archive",
+                source
+            ))),
+            None => Ok((
+                file_name.map(|name| strip_compression_extension(&name).to_string()),
+                body,
+            )),
+        }
+    }
 
-        let schema = format!("CREATE TABLE x({});", columns_def);
-        let base: sqlite3_vtab = unsafe { mem::zeroed() };
-        Ok((
-            schema,
-            UrlTable {
-                base,
-                df,
-                headers,
-                columns_types,
-            },
-        ))
+    fn read_dataframe(
+        format: &VTabDataFormats,
+        resp: Vec<u8>,
+        opts: &TableOpts,
+        csv_opts: &CsvOpts,
+    ) -> Result<DataFrame> {
+        // The records selected by RECORD_PATH are read as a single JSON array.
+        let (format, resp) = match (format, &opts.record_path) {
+            (VTabDataFormats::JSON | VTabDataFormats::JSONL, Some(path)) => {
+                let records = path
+                    .select(&resp, *format == VTabDataFormats::JSONL)
+                    .map_err(|e| Error::new_message(format!("JSON build error: {}", e)))?;
+                (&VTabDataFormats::JSON, records)
+            }
+            _ => (format, resp),
+        };
+        let df = match format {
+            VTabDataFormats::CSV => read_csv(resp, csv_opts)
+                .map_err(|e| Error::new_message(&format!("CSV parse error: {}", e)))?,
+            VTabDataFormats::PARQUET => ParquetReader::new(std::io::Cursor::new(resp))
+                .finish()
+                .map_err(|e| Error::new_message(format!("Parquet parse error: {}", e)))?,
+            VTabDataFormats::AVRO => AvroReader::new(&resp)
+                .finish()
+                .map_err(|e| Error::new_message(&format!("Avro build error: {}", e)))?,
+            VTabDataFormats::JSON => JsonReader::new(std::io::Cursor::new(resp))
+                .with_json_format(JsonFormat::Json)
+                .finish()
+                .map_err(|e| Error::new_message(&format!("JSON build error: {}", e)))?,
+            VTabDataFormats::JSONL => JsonReader::new(std::io::Cursor::new(resp))
+                .with_json_format(JsonFormat::JsonLines)
+                .finish()
+                .map_err(|e| Error::new_message(&format!("JSON build error: {}", e)))?,
+            // Compressed buffers, LZ4 or ZSTD, are decompressed by the readers.
+            VTabDataFormats::ARROW | VTabDataFormats::FEATHER => {
+                IpcReader::new(std::io::Cursor::new(resp))
+                    .finish()
+                    .map_err(|e| Error::new_message(format!("Arrow IPC parse error: {}", e)))?
+            }
+            VTabDataFormats::IPC_STREAM => IpcStreamReader::new(std::io::Cursor::new(resp))
+                .finish()
+                .map_err(|e| Error::new_message(format!("Arrow IPC parse error: {}", e)))?,
+            VTabDataFormats::XLSX | VTabDataFormats::XLS | VTabDataFormats::ODS => {
+                SpreadsheetReader::new(&resp, *format)
+                    .with_sheet(opts.sheet.as_deref())
+                    .with_range(opts.range)
+                    .finish()
+                    .map_err(|e| Error::new_message(format!("Spreadsheet parse error: {}", e)))?
+            }
+        };
+        Ok(df)
     }
 
     fn dataframe_from_rows(
@@ -411,59 +912,171 @@ impl<'vtab> VTab<'vtab> for UrlTable {
     }
 
     fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
-        let mut used_cols = Vec::new();
-        let mut used_ops = Vec::new();
-
-        for (_i, constraint) in info.constraints().iter_mut().enumerate() {
-            if constraint.usable() {
-                let op = match constraint.op() {
-                    Some(ConstraintOperator::EQ) => "=",
-                    Some(ConstraintOperator::GT) => ">",
-                    Some(ConstraintOperator::LT) => "<",
-                    Some(ConstraintOperator::GE) => ">=",
-                    Some(ConstraintOperator::LE) => "<=",
-                    Some(ConstraintOperator::NE) => "!=",
-                    _ => continue,
-                };
+        let mut plan = QueryPlan::default();
+
+        // SQLite only hands over ORDER BY terms on plain columns with their
+        // default collation and NULL placement, so Polars can sort them all
+        // unless one is the rowid.
+        let order_bys = info.order_bys();
+        let order_consumed = !order_bys.is_empty()
+            && order_bys
+                .iter()
+                .all(|order_by| (0..self.headers.len() as i32).contains(&order_by.icolumn()));
+        if order_consumed {
+            plan.order = order_bys
+                .iter()
+                .map(|order_by| OrderTerm {
+                    column: order_by.icolumn() as usize,
+                    descending: matches!(order_by.direction(), OrderByDirection::Descending),
+                })
+                .collect();
+        }
 
-                constraint.set_argv_index((used_cols.len() + 1) as i32); // 1-based
-                used_cols.push(constraint.column_idx());
-                used_ops.push(op);
+        // LIMIT and OFFSET apply to the rows we return, so they can only be
+        // consumed when SQLite has no other constraint left to check on them
+        // and no sorting left to do.
+        let limit_pushable = (order_bys.is_empty() || order_consumed)
+            && info.constraints().iter().all(|constraint| {
+                matches!(
+                    constraint.op(),
+                    Some(ConstraintOperator::LIMIT) | Some(ConstraintOperator::OFFSET)
+                )
+            });
+
+        // Numbers compared against, when known while planning, for the
+        // row estimate.
+        let mut rhs_numbers = Vec::new();
+        let mut builtin_patterns = None;
+        for (i, constraint) in info.constraints().iter_mut().enumerate() {
+            if !constraint.usable() {
+                continue;
             }
+            let argv_index = plan.argc() + 1;
+            let op = match constraint.op() {
+                Some(ConstraintOperator::LIMIT) if limit_pushable => {
+                    constraint.set_argv_index(argv_index as i32);
+                    plan.limit = Some(argv_index);
+                    continue;
+                }
+                Some(ConstraintOperator::OFFSET) if limit_pushable => {
+                    // Otherwise SQLite skips the first rows once more.
+                    constraint.set_omit(true);
+                    constraint.set_argv_index(argv_index as i32);
+                    plan.offset = Some(argv_index);
+                    continue;
+                }
+                // REGEXP and MATCH are left to the functions that define
+                // them, their syntax need not be the one of Rust regexes.
+                Some(op) => match PlanOp::from_operator(&op) {
+                    Some(op) => op,
+                    None => continue,
+                },
+                None => continue,
+            };
+            let column = match usize::try_from(constraint.column_idx()) {
+                Ok(column) if column < self.headers.len() => column,
+                _ => continue,
+            };
+
+            // Comparisons follow the collating sequence, Polars only knows
+            // BINARY and can approximate NOCASE equality.
+            let collation = index_info::collation(&info, i);
+            if op.compares()
+                && collation != "BINARY"
+                && !(collation == "NOCASE" && op == PlanOp::EQ)
+            {
+                continue;
+            }
+
+            // An overridden like() or glob() has rules Polars can't follow.
+            if matches!(op, PlanOp::LIKE | PlanOp::GLOB)
+                && !*builtin_patterns.get_or_insert_with(|| builtin_pattern_functions(self.db))
+            {
+                continue;
+            }
+
+            // An IN list arrives as EQ, ask for all its values at once.
+            let op = match op {
+                PlanOp::EQ if constraint.can_process_all_in() => {
+                    constraint.enable_process_all_in();
+                    PlanOp::IN
+                }
+                op => op,
+            };
+
+            constraint.set_argv_index(argv_index as i32);
+            rhs_numbers.push(index_info::rhs_number(&info, i));
+            plan.constraints.push(PlanConstraint {
+                column,
+                op,
+                argv_index,
+                collation,
+            });
         }
 
-        let idx_str = used_cols
-            .iter()
-            .zip(used_ops.iter())
-            .map(|(col, op)| format!("{}{}", col, op))
-            .collect::<Vec<String>>()
-            .join(",");
+        // Bit 63 of colUsed stands for every column from the 64th on.
+        let columns_used = info.columns_used();
+        plan.columns = (0..self.headers.len())
+            .filter(|i| columns_used & (1 << (*i).min(63)) != 0)
+            .collect();
 
-        let _ = info.set_idxstr(&idx_str);
-        info.set_idxnum(used_cols.len() as i32);
+        let idx_str = plan.serialize().map_err(|_| BestIndexError::Error)?;
+        info.set_idxstr(&idx_str)
+            .map_err(|_| BestIndexError::Error)?;
+        info.set_idxnum(plan.argc() as i32);
+        if order_consumed {
+            index_info::set_order_by_consumed(&info);
+        }
+
+        // Filtering the DataFrame is cheap next to handing rows to SQLite one
+        // by one, a REMOTE table also downloads what it scans.
+        let stats = self.stats().map_err(|_| BestIndexError::Error)?;
+        let rows = stats.estimate_rows(
+            plan.constraints
+                .iter()
+                .zip(rhs_numbers)
+                .map(|(c, value)| (c.column, c.op, value)),
+        );
+        let scan = match self.remote {
+            Some(_) => stats.rows as f64,
+            None => stats.rows as f64 / 100.0,
+        };
+        info.set_estimated_rows(rows as i64);
+        info.set_estimated_cost(scan + rows);
 
         Ok(())
     }
 
     fn open(&mut self) -> Result<UrlCursor> {
-        Ok(UrlCursor::new(self.df.clone()))
+        Ok(UrlCursor::new(self.snapshot()?))
     }
 }
 
+/// Column appended to the rows of a scan with their position in the table.
+const ROWID: &str = "__httpfs_rowid";
+
 #[repr(C)]
 struct UrlCursor {
     base: sqlite3_vtab_cursor,
     row_idx: usize,
     filtered_df: DataFrame,
+    /// Position in `filtered_df` of every table column, `None` for the
+    /// columns the query does not read.
+    columns: Vec<Option<usize>>,
+    /// Row groups of a `REMOTE` table read by earlier `filter` calls.
+    row_groups: RowGroupCache,
 }
 
 impl UrlCursor {
     fn new(df: DataFrame) -> UrlCursor {
         let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        let columns = (0..df.width()).map(Some).collect();
         UrlCursor {
             base,
             row_idx: 0,
             filtered_df: df,
+            columns,
+            row_groups: RowGroupCache::default(),
         }
     }
 }
@@ -476,81 +1089,184 @@ impl VTabCursor for UrlCursor {
         args: &[*mut sqlite3_value],
     ) -> Result<()> {
         let vtab: &UrlTable = unsafe { &*(self.base.pVtab as *mut UrlTable) };
-        let mut lf = vtab.df.clone().lazy();
-
-        if let Some(idx_str) = idx_str {
-            for (i, part) in idx_str.split(',').enumerate() {
-                let trimmed = part.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
-
-                let (col_str, op) = if trimmed.ends_with('=') {
-                    trimmed.split_at(trimmed.len() - 1)
-                } else {
-                    trimmed.split_at(trimmed.len())
-                };
-
-                if col_str.is_empty() {
-                    continue;
-                }
-
-                let col_idx: usize = match col_str.parse::<usize>() {
-                    Ok(idx) => idx,
-                    Err(_) => continue,
-                };
-
-                let col_name = &vtab.headers[col_idx];
-                let col_type: &DataType = &vtab.df.dtypes()[col_idx];
-                let arg: *mut sqlite3_value = args[i];
-
-                let filter_value = match col_type {
-                    DataType::Boolean => {
-                        let val = api::value_int(&arg);
-                        lit(val != 0)
-                    }
-                    DataType::UInt8
-                    | DataType::UInt16
-                    | DataType::UInt32
-                    | DataType::UInt64
-                    | DataType::Int8
-                    | DataType::Int16
-                    | DataType::Int32
-                    | DataType::Int64 => {
-                        let val = api::value_int64(&arg);
-                        lit(val)
-                    }
-                    DataType::Float32 | DataType::Float64 => {
-                        let val = api::value_double(&arg);
-                        lit(val)
+        let base_df = vtab.snapshot()?;
+        let dtypes = base_df.dtypes();
+        let plan = match idx_str {
+            Some(idx_str) => {
+                QueryPlan::deserialize(idx_str).map_err(|e| Error::new_message(format!("{}", e)))?
+            }
+            None => QueryPlan {
+                columns: (0..vtab.headers.len()).collect(),
+                ..QueryPlan::default()
+            },
+        };
+        let arg = |argv_index: usize| {
+            args.get(argv_index.wrapping_sub(1))
+                .copied()
+                .ok_or_else(|| Error::new_message("Query plan argument out of range"))
+        };
+        // Predicates on a local Parquet file are built on the columns as
+        // Polars reads them, so they reach the scan.
+        let local_scan = match vtab.remote.as_ref().and_then(RangeReader::local_path) {
+            Some(path) => Some(
+                scan_local(path, ROWID, base_df.schema())
+                    .map_err(|e| Error::new_message(format!("Parquet read error: {}", e)))?,
+            ),
+            None => None,
+        };
+        let column = |name: &str| match &local_scan {
+            Some(scan) => scan.column(name),
+            None => col(name),
+        };
+        let mut filters = Vec::new();
+        let mut row_group_filters = Vec::new();
+
+        for constraint in &plan.constraints {
+            let col_name = vtab
+                .headers
+                .get(constraint.column)
+                .ok_or_else(|| Error::new_message("Invalid column index"))?;
+            let col_type: &DataType = &dtypes[constraint.column];
+            let op = constraint.op;
+            let arg = arg(constraint.argv_index)?;
+
+            match op {
+                PlanOp::LIKE | PlanOp::GLOB => {
+                    // SQLite converts other types to text first, leave them to it.
+                    if col_type != &DataType::String
+                        || matches!(api::value_type(&arg), api::ValueType::Null)
+                    {
+                        continue;
                     }
-                    DataType::String => {
-                        let val = api::value_text(&arg)?;
-                        lit(val.to_string())
+                    let pattern = api::value_text(&arg)?;
+                    let regex = match op {
+                        PlanOp::LIKE => Some(like_to_regex(pattern)),
+                        _ => glob_to_regex(pattern),
+                    };
+                    if let Some(regex) = regex.filter(|regex| Regex::new(regex).is_ok()) {
+                        filters.push(column(col_name).str().contains(lit(regex), true));
                     }
-                    _ => {
-                        let val = api::value_text(&arg)?;
-                        lit(val.to_string())
+                }
+                PlanOp::ISNULL | PlanOp::ISNOTNULL => {
+                    // These have no right-hand value, only the column matters.
+                    filters.push(match op {
+                        PlanOp::ISNULL => column(col_name).is_null(),
+                        _ => column(col_name).is_not_null(),
+                    });
+                    row_group_filters.push(RowGroupFilter {
+                        column: col_name.clone(),
+                        op,
+                        value: AnyValue::Null,
+                    });
+                }
+                PlanOp::EQ | PlanOp::IN if constraint.collation == "NOCASE" => {
+                    // NOCASE folds ASCII letters only, lower-casing everything
+                    // keeps at least the rows SQLite matches.
+                    if col_type != &DataType::String {
+                        continue;
                     }
-                };
-
-                let filter_expr = match op {
-                    "=" => col(col_name).eq(filter_value),
-                    ">" => col(col_name).gt(filter_value),
-                    "<" => col(col_name).lt(filter_value),
-                    ">=" => col(col_name).gt_eq(filter_value),
-                    "<=" => col(col_name).lt_eq(filter_value),
-                    "!" => col(col_name).neq(filter_value),
-                    _ => continue,
-                };
+                    let values =
+                        rhs_values(arg, op, |value| Ok(api::value_text(value)?.to_lowercase()))?;
+                    let values = Series::new(col_name.into(), values);
+                    filters.push(column(col_name).str().to_lowercase().is_in(lit(values)));
+                }
+                _ if constraint.collation != "BINARY" => continue,
+                PlanOp::IN => {
+                    let values = rhs_values(arg, op, |value| Ok(arg_value(value, col_type)?.1))?;
+                    let values = Series::from_any_values(col_name.into(), &values, false)
+                        .map_err(|e| Error::new_message(format!("Polars error: {}", e)))?;
+                    filters.push(column(col_name).is_in(lit(values)));
+                }
+                _ => {
+                    let (filter_value, any_value) = arg_value(&arg, col_type)?;
+                    filters.push(match op {
+                        PlanOp::EQ => column(col_name).eq(filter_value),
+                        PlanOp::GT => column(col_name).gt(filter_value),
+                        PlanOp::LT => column(col_name).lt(filter_value),
+                        PlanOp::GE => column(col_name).gt_eq(filter_value),
+                        PlanOp::LE => column(col_name).lt_eq(filter_value),
+                        _ => column(col_name).neq(filter_value),
+                    });
+                    row_group_filters.push(RowGroupFilter {
+                        column: col_name.clone(),
+                        op,
+                        value: any_value,
+                    });
+                }
+            }
+        }
 
-                lf = lf.filter(filter_expr);
+        // A negative LIMIT means no limit at all.
+        let limit = match plan.limit {
+            Some(i) => IdxSize::try_from(api::value_int64(&arg(i)?)).ok(),
+            None => None,
+        };
+        let offset = match plan.offset {
+            Some(i) => api::value_int64(&arg(i)?).max(0),
+            None => 0,
+        };
+        let order = plan
+            .order
+            .iter()
+            .map(|term| (vtab.headers[term.column].clone(), term.descending))
+            .collect::<Vec<_>>();
+        let used_columns = plan.columns;
+
+        let mut lf = match (local_scan, vtab.remote.as_ref()) {
+            // Polars reads local files itself and takes the filters, columns
+            // and slice into the scan.
+            (Some(scan), _) => scan
+                .finish(mem::take(&mut filters))
+                .map_err(|e| Error::new_message(format!("Parquet read error: {}", e)))?,
+            (None, Some(reader)) => {
+                let mut parquet_reader = ParquetRangeReader::new(reader.clone());
+                match limit {
+                    Some(limit) if row_group_filters.is_empty() && order.is_empty() => {
+                        parquet_reader = parquet_reader.with_limit(offset as usize + limit as usize)
+                    }
+                    _ => parquet_reader = parquet_reader.with_filters(row_group_filters),
+                }
+                let names = used_columns.iter().map(|i| vtab.headers[*i].clone());
+                parquet_reader
+                    .with_columns(names.collect())
+                    .with_row_index(ROWID.to_string())
+                    .finish(&mut self.row_groups)
+                    .map_err(|e| Error::new_message(format!("Parquet read error: {}", e)))?
+                    .lazy()
             }
+            (None, None) => base_df.lazy().with_row_index(ROWID, None),
+        };
+        for filter_expr in filters {
+            lf = lf.filter(filter_expr);
+        }
+        if !order.is_empty() {
+            // SQLite puts NULLs first in ascending order and last in descending.
+            let (columns, descending): (Vec<_>, Vec<_>) = order.into_iter().unzip();
+            lf = lf.sort(
+                columns,
+                SortMultipleOptions::default()
+                    .with_order_descending_multi(descending.clone())
+                    .with_nulls_last_multi(descending),
+            );
+        }
+        if limit.is_some() || offset > 0 {
+            lf = lf.slice(offset, limit.unwrap_or(IdxSize::MAX));
         }
 
+        let mut selection = used_columns
+            .iter()
+            .map(|i| col(vtab.headers[*i].as_str()))
+            .collect::<Vec<_>>();
+        selection.push(col(ROWID));
         self.filtered_df = lf
+            .select(selection)
             .collect()
             .map_err(|e| Error::new_message(&format!("Polars collect error: {}", e)))?;
+        // Scans may put their row index first, look the columns up by name.
+        self.columns = vec![None; vtab.headers.len()];
+        for i in used_columns {
+            self.columns[i] = self.filtered_df.get_column_index(&vtab.headers[i]);
+        }
         self.row_idx = 0;
 
         Ok(())
@@ -566,35 +1282,149 @@ impl VTabCursor for UrlCursor {
     }
 
     fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
-        let col = self
-            .filtered_df
-            .select_at_idx(i as usize)
-            .ok_or_else(|| Error::new_message("Invalid column index"))?;
-        let val = col.get(self.row_idx);
-
-        match val {
-            Ok(AnyValue::Int64(v)) => api::result_int64(ctx, v),
-            Ok(AnyValue::Int32(v)) => api::result_int64(ctx, v as i64),
-            Ok(AnyValue::Float64(v)) => api::result_double(ctx, v),
-            Ok(AnyValue::Float32(v)) => api::result_double(ctx, v as f64),
-            Ok(AnyValue::Boolean(v)) => api::result_int(ctx, if v { 1 } else { 0 }),
-            Ok(AnyValue::String(v)) => api::result_text(ctx, v)?,
-            Ok(AnyValue::StringOwned(v)) => api::result_text(ctx, &v)?,
-            Ok(AnyValue::Null) => api::result_null(ctx),
-            Ok(v) => api::result_text(ctx, &v.to_string())?,
-            Err(_) => api::result_null(ctx),
+        let col = match self.columns.get(i as usize) {
+            Some(Some(position)) => self.filtered_df.select_at_idx(*position),
+            Some(None) => {
+                api::result_null(ctx);
+                return Ok(());
+            }
+            None => None,
         }
-
-        Ok(())
+        .ok_or_else(|| Error::new_message("Invalid column index"))?;
+        result_value(ctx, col.get(self.row_idx))
     }
 
     fn rowid(&self) -> Result<i64> {
-        Ok(self.row_idx as i64)
+        // OR constraints are answered by several scans whose rows SQLite
+        // tells apart by rowid, it has to stay the same in every one of them.
+        let rowid = self
+            .filtered_df
+            .column(ROWID)
+            .ok()
+            .and_then(|column| column.get(self.row_idx).ok())
+            .and_then(|value| value.extract::<i64>());
+        Ok(rowid.unwrap_or(self.row_idx as i64))
     }
 }
 
-#[sqlite_entrypoint]
-pub fn sqlite3_httpfs_init(db: *mut sqlite3) -> Result<()> {
+/// Hands a DataFrame value to SQLite as the result of a column.
+fn result_value(ctx: *mut sqlite3_context, val: PolarsResult<AnyValue>) -> Result<()> {
+    match val {
+        Ok(AnyValue::Int64(v)) => api::result_int64(ctx, v),
+        Ok(AnyValue::Int32(v)) => api::result_int64(ctx, v as i64),
+        Ok(AnyValue::Float64(v)) => api::result_double(ctx, v),
+        Ok(AnyValue::Float32(v)) => api::result_double(ctx, v as f64),
+        Ok(AnyValue::Boolean(v)) => api::result_int(ctx, if v { 1 } else { 0 }),
+        Ok(AnyValue::String(v)) => api::result_text(ctx, v)?,
+        Ok(AnyValue::StringOwned(v)) => api::result_text(ctx, &v)?,
+        Ok(AnyValue::Null) => api::result_null(ctx),
+        Ok(v) => api::result_text(ctx, &v.to_string())?,
+        Err(_) => api::result_null(ctx),
+    }
+
+    Ok(())
+}
+
+/// The right-hand values of an `=` or `IN` constraint converted with
+/// `convert`, leaving out NULLs, which never compare equal. SQLite reuses
+/// the same value for every element of an `IN` list, so each one has to be
+/// converted before moving to the next.
+fn rhs_values<T>(
+    arg: *mut sqlite3_value,
+    op: PlanOp,
+    convert: impl Fn(&*mut sqlite3_value) -> Result<T>,
+) -> Result<Vec<T>> {
+    let values: Box<dyn Iterator<Item = Result<*mut sqlite3_value>>> = match op {
+        PlanOp::IN => Box::new(InValues::new(arg)),
+        _ => Box::new(std::iter::once(Ok(arg))),
+    };
+    let mut converted = Vec::new();
+    for value in values {
+        let value = value?;
+        if !matches!(api::value_type(&value), api::ValueType::Null) {
+            converted.push(convert(&value)?);
+        }
+    }
+    Ok(converted)
+}
+
+/// Reads a constraint argument as a literal of the column type, along with
+/// the same value for row group pruning.
+fn arg_value(arg: &*mut sqlite3_value, col_type: &DataType) -> Result<(Expr, AnyValue<'static>)> {
+    Ok(match col_type {
+        DataType::Boolean => {
+            let val = api::value_int(arg);
+            (lit(val != 0), AnyValue::Boolean(val != 0))
+        }
+        DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64 => match api::value_type(arg) {
+            // Truncating 2.5 to 2 would change what `<`, `>` and `!=` match,
+            // Polars compares the column as floats instead.
+            api::ValueType::Float => {
+                let val = api::value_double(arg);
+                (lit(val), AnyValue::Float64(val))
+            }
+            _ => {
+                let val = api::value_int64(arg);
+                (lit(val), AnyValue::Int64(val))
+            }
+        },
+        DataType::Float32 | DataType::Float64 => {
+            let val = api::value_double(arg);
+            (lit(val), AnyValue::Float64(val))
+        }
+        DataType::String => {
+            let val = api::value_text(arg)?;
+            (lit(val.to_string()), AnyValue::StringOwned(val.into()))
+        }
+        _ => {
+            let val = api::value_text(arg)?;
+            (lit(val.to_string()), AnyValue::StringOwned(val.into()))
+        }
+    })
+}
+
+/// Whether `like()` and `glob()` are SQLite's own. `PRAGMA
+/// case_sensitive_like` and extensions such as ICU register their own
+/// versions, which `pragma_function_list` does not mark as built in.
+fn builtin_pattern_functions(db: *mut sqlite3) -> bool {
+    let sql = "SELECT 1 FROM pragma_function_list WHERE name IN ('like', 'glob') AND NOT builtin;";
+    Statement::build(db, sql)
+        .and_then(|stmt| stmt.fetch(1))
+        .is_ok_and(|rows| rows.is_empty())
+}
+
+fn httpfs_init(db: *mut sqlite3) -> Result<()> {
     define_virtual_table::<UrlTable>(db, "httpfs", None)?;
+    define_table_function::<query::QueryTable>(db, "httpfs_query", None)?;
+    define_scalar_function(
+        db,
+        "httpfs_refresh",
+        1,
+        refresh::httpfs_refresh,
+        // Refetches data, so it is not deterministic and must not run from
+        // views, triggers or schema expressions.
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
     Ok(())
 }
+
+/// # Safety
+///
+/// Should only be called by SQLite when loading the extension. Written out
+/// instead of `#[sqlite_entrypoint]` to keep the API pointer, see `index_info`.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_httpfs_init(
+    db: *mut sqlite3,
+    pz_err_msg: *mut *mut c_char,
+    p_api: *mut sqlite3_api_routines,
+) -> c_uint {
+    index_info::set_api(p_api);
+    register_entrypoint(db, pz_err_msg, p_api, httpfs_init)
+}
@@ -0,0 +1,1260 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_cast::cast;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema};
+use bytes::Bytes;
+use parquet::{
+    arrow::{
+        arrow_reader::{
+            ArrowReaderMetadata, ArrowReaderOptions, ParquetRecordBatchReader,
+            ParquetRecordBatchReaderBuilder,
+        },
+        ProjectionMask,
+    },
+    basic::{ConvertedType, LogicalType, Repetition, Type as PhysicalType},
+    errors::ParquetError,
+    file::{
+        metadata::RowGroupMetaData,
+        reader::{ChunkReader, FileReader, Length},
+        serialized_reader::SerializedFileReader,
+        statistics::Statistics,
+    },
+    schema::types::Type,
+};
+use polars::{
+    io::{HiveOptions, RowIndex},
+    prelude::{
+        col, AnyValue, Column, DataFrame, DataType, Expr, GetOutput, IdxSize, IntoColumn,
+        IpcStreamReader, LazyFrame, PlSmallStr, PolarsError, PolarsResult, ScanArgsParquet, Schema,
+        SerReader, StringChunked, TimeUnit,
+    },
+};
+
+use crate::{
+    fetch::{fetch_range, DataSource, FetchOpts},
+    plan::PlanOp,
+    stats::{ColumnStats, TableStats},
+};
+
+/// Size of the first request, big enough to hold the footer of most files.
+const FOOTER_PREFETCH: u64 = 64 * 1024;
+/// Size of the reads issued for page headers that are not cached yet.
+const READ_AHEAD: u64 = 64 * 1024;
+/// Column chunks closer than this are fetched with a single request.
+const COALESCE_GAP: u64 = 1024 * 1024;
+
+/// Byte ranges fetched from a remote source. The footer is kept for the life
+/// of the reader; data ranges only until [`RangeReader::release`], called
+/// once their row group is decoded, so a scan holds one row group at a time.
+#[derive(Default)]
+struct RangeCache {
+    footer: Option<(u64, Bytes)>,
+    chunks: Vec<(u64, Bytes)>,
+}
+
+impl RangeCache {
+    /// Cached bytes from `start` up to the end of the chunk holding it.
+    fn get(&self, start: u64, len: u64) -> Option<Bytes> {
+        self.footer
+            .iter()
+            .chain(&self.chunks)
+            .find_map(|(chunk_start, chunk)| {
+                let chunk_end = chunk_start + chunk.len() as u64;
+                (*chunk_start <= start && start + len <= chunk_end)
+                    .then(|| chunk.slice((start - chunk_start) as usize..))
+            })
+    }
+}
+
+/// [`ChunkReader`] that only reads the byte ranges it is asked for, through
+/// HTTP `Range` requests for remote sources. Fetched ranges are cached and
+/// shared between clones: the footer for later queries, the data of a row
+/// group while it is decoded.
+#[derive(Clone)]
+pub struct RangeReader {
+    source: DataSource,
+    opts: FetchOpts,
+    len: u64,
+    /// ETag of the footer, the ranges read later must come from the same
+    /// version of the file.
+    etag: Option<String>,
+    cache: Arc<Mutex<RangeCache>>,
+}
+
+impl RangeReader {
+    pub fn new(source: DataSource, opts: FetchOpts) -> Result<Self, Box<dyn Error>> {
+        let mut cache = RangeCache::default();
+        let (len, etag) = match &source {
+            DataSource::HTTP(url) => {
+                let resp = fetch_range(url, &opts, &format!("bytes=-{}", FOOTER_PREFETCH))?;
+                cache.footer = Some((resp.start, Bytes::from(resp.body)));
+                (resp.total_len, resp.etag)
+            }
+            DataSource::FILE(path) => {
+                let len = std::fs::metadata(path)
+                    .map_err(|e| format!("File error: {}: {}", path.display(), e))?
+                    .len();
+                (len, None)
+            }
+        };
+
+        Ok(Self {
+            source,
+            opts,
+            len,
+            etag,
+            cache: Arc::new(Mutex::new(cache)),
+        })
+    }
+
+    fn cached(&self, start: u64, len: u64) -> Option<Bytes> {
+        self.cache.lock().ok()?.get(start, len)
+    }
+
+    /// Drops the cached data ranges, keeping the footer.
+    fn release(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.chunks.clear();
+        }
+    }
+
+    fn read_range(&self, start: u64, end: u64) -> Result<Bytes, Box<dyn Error>> {
+        let end = end.min(self.len);
+        if start >= end {
+            return Ok(Bytes::new());
+        }
+        if let Some(bytes) = self.cached(start, end.saturating_sub(start)) {
+            return Ok(bytes.slice(..(end - start) as usize));
+        }
+
+        match &self.source {
+            DataSource::HTTP(url) => {
+                let resp = fetch_range(url, &self.opts, &format!("bytes={}-{}", start, end - 1))?;
+                if self.etag.is_some() && resp.etag != self.etag {
+                    return Err(
+                        format!("HTTP error: {} changed while it was read", self.source).into(),
+                    );
+                }
+                let chunk = Bytes::from(resp.body);
+                let offset = start
+                    .checked_sub(resp.start)
+                    .filter(|offset| offset + (end - start) <= chunk.len() as u64)
+                    .ok_or("HTTP error: server returned a different range")?;
+                let bytes = chunk.slice(offset as usize..(offset + end - start) as usize);
+                self.cache
+                    .lock()
+                    .map_err(|_| "Range cache poisoned")?
+                    .chunks
+                    .push((resp.start, chunk));
+                Ok(bytes)
+            }
+            DataSource::FILE(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(start))?;
+                let mut buf = vec![0; (end - start) as usize];
+                file.read_exact(&mut buf)?;
+                Ok(Bytes::from(buf))
+            }
+        }
+    }
+
+    /// Path of the file when the source is local.
+    pub fn local_path(&self) -> Option<&Path> {
+        match &self.source {
+            DataSource::FILE(path) => Some(path),
+            DataSource::HTTP(_) => None,
+        }
+    }
+
+    /// Fetches the given `(start, length)` ranges ahead of time, merging
+    /// nearby ranges so a row group costs a handful of requests.
+    fn prefetch(&self, ranges: Vec<(u64, u64)>) -> Result<(), Box<dyn Error>> {
+        if let DataSource::HTTP(_) = self.source {
+            for (start, end) in coalesce_ranges(ranges, COALESCE_GAP) {
+                self.read_range(start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns `(start, length)` ranges into sorted `(start, end)` ranges, merging
+/// the ones separated by less than `gap` bytes.
+fn coalesce_ranges(mut ranges: Vec<(u64, u64)>, gap: u64) -> Vec<(u64, u64)> {
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, len) in ranges {
+        let end = start + len;
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end + gap => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+impl Length for RangeReader {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl ChunkReader for RangeReader {
+    type T = Cursor<Bytes>;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<Self::T> {
+        let bytes = match self.cached(start, 1) {
+            Some(bytes) => bytes,
+            None => self
+                .read_range(start, start + READ_AHEAD)
+                .map_err(|e| ParquetError::General(e.to_string()))?,
+        };
+        Ok(Cursor::new(bytes))
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        self.read_range(start, start + length as u64)
+            .map_err(|e| ParquetError::General(e.to_string()))
+    }
+}
+
+/// Scans a local Parquet file with Polars, with the position of every row
+/// in the `row_index` column.
+///
+/// Columns come back with the types of `schema`, the one
+/// [`ParquetRangeReader::schema`] declares, rather than the types Polars
+/// reads them as.
+pub fn scan_local(path: &Path, row_index: &str, schema: &Schema) -> PolarsResult<LocalScan> {
+    let args = ScanArgsParquet {
+        row_index: Some(RowIndex {
+            name: row_index.into(),
+            offset: 0,
+        }),
+        hive_options: HiveOptions {
+            enabled: Some(false),
+            ..Default::default()
+        },
+        glob: false,
+        ..Default::default()
+    };
+    let mut lf = LazyFrame::scan_parquet(path, args)?;
+    let converted = lf
+        .collect_schema()?
+        .iter()
+        .filter_map(|(name, dtype)| {
+            let declared = schema.get(name).filter(|declared| *declared != dtype)?;
+            let column = match declared {
+                // Nested values and decimals are declared as text.
+                DataType::String => col(name.clone()).map(
+                    |column| values_to_text(column).map(Some),
+                    GetOutput::from_type(DataType::String),
+                ),
+                declared => col(name.clone()).cast(declared.clone()),
+            };
+            Some((name.clone(), column.alias(name.clone())))
+        })
+        .collect();
+
+    Ok(LocalScan { lf, converted })
+}
+
+/// Lazy scan of a local Parquet file, see [`scan_local`].
+pub struct LocalScan {
+    lf: LazyFrame,
+    /// Expressions giving their declared type to the columns Polars reads
+    /// with another one.
+    converted: HashMap<PlSmallStr, Expr>,
+}
+
+impl LocalScan {
+    /// Expression of the column `name` with its declared type, to build the
+    /// predicates given to [`LocalScan::finish`].
+    pub fn column(&self, name: &str) -> Expr {
+        match self.converted.get(name) {
+            Some(column) => column.clone(),
+            None => col(name),
+        }
+    }
+
+    /// Filters the scan, then gives every column its declared type. The
+    /// predicates apply to the columns as Polars reads them, so they are
+    /// pushed down into the scan, which skips the row groups whose
+    /// statistics rule them out.
+    pub fn finish(self, predicates: Vec<Expr>) -> PolarsResult<LazyFrame> {
+        let mut lf = self.lf;
+        for predicate in predicates {
+            lf = lf.filter(predicate);
+        }
+        if self.converted.is_empty() {
+            return Ok(lf);
+        }
+        let columns = lf
+            .collect_schema()?
+            .iter_names()
+            .map(|name| match self.converted.get(name) {
+                Some(column) => column.clone(),
+                None => col(name.clone()),
+            })
+            .collect::<Vec<_>>();
+        Ok(lf.select(columns))
+    }
+}
+
+/// Renders every value of `column` as text.
+fn values_to_text(column: Column) -> PolarsResult<Column> {
+    let text = column
+        .as_materialized_series()
+        .iter()
+        .map(|value| match value {
+            AnyValue::Null => None,
+            value => Some(value.to_string()),
+        })
+        .collect::<StringChunked>()
+        .with_name(column.name().clone());
+    Ok(text.into_column())
+}
+
+/// Constraint used to skip row groups whose statistics can't match it.
+#[derive(Debug, Clone)]
+pub struct RowGroupFilter {
+    pub column: String,
+    pub op: PlanOp,
+    pub value: AnyValue<'static>,
+}
+
+/// Row groups decoded by earlier reads, by index, all with the same
+/// columns. Kept by a cursor, whose `filter` a join calls once per outer
+/// row, so the row groups are only downloaded once.
+#[derive(Default)]
+pub struct RowGroupCache {
+    projected: Vec<usize>,
+    row_groups: HashMap<usize, DataFrame>,
+}
+
+/// Reads a Parquet file through a [`RangeReader`], downloading only the
+/// footer, the requested columns and the row groups that may match the
+/// filters.
+pub struct ParquetRangeReader {
+    reader: RangeReader,
+    columns: Option<Vec<String>>,
+    filters: Vec<RowGroupFilter>,
+    limit: Option<usize>,
+    row_index: Option<String>,
+}
+
+impl ParquetRangeReader {
+    pub fn new(reader: RangeReader) -> Self {
+        Self {
+            reader,
+            columns: None,
+            filters: Vec::new(),
+            limit: None,
+            row_index: None,
+        }
+    }
+
+    /// Only decode these columns, the others are returned as nulls.
+    pub fn with_columns(mut self, columns: Vec<String>) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    pub fn with_filters(mut self, filters: Vec<RowGroupFilter>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Stops reading row groups once `limit` rows were read. The result may
+    /// hold more rows than that, up to the end of the last row group.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Appends a column holding the position of every row in the file.
+    pub fn with_row_index(mut self, name: String) -> Self {
+        self.row_index = Some(name);
+        self
+    }
+
+    pub fn schema(&self) -> PolarsResult<Schema> {
+        let reader = SerializedFileReader::new(self.reader.clone()).map_err(to_polars_err)?;
+        let root = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema();
+
+        Ok(root
+            .get_fields()
+            .iter()
+            .map(|field| (field.name().into(), field_dtype(field)))
+            .collect())
+    }
+
+    /// Row count, null counts and numeric bounds from the footer, distinct
+    /// counts are rarely written and left unknown.
+    pub fn stats(&self) -> PolarsResult<TableStats> {
+        let reader = SerializedFileReader::new(self.reader.clone()).map_err(to_polars_err)?;
+        let metadata = reader.metadata();
+        let schema = metadata.file_metadata().schema_descr();
+        let columns = schema
+            .root_schema()
+            .get_fields()
+            .iter()
+            .map(|field| {
+                let leaf = (0..schema.num_columns())
+                    .find(|i| schema.column(*i).path().parts() == [field.name()]);
+                let nulls = leaf.and_then(|i| {
+                    metadata
+                        .row_groups()
+                        .iter()
+                        .map(|row_group| row_group.column(i).statistics()?.null_count_opt())
+                        .sum::<Option<u64>>()
+                });
+                // Known only when every row group has them.
+                let bounds = leaf.and_then(|i| {
+                    let converted = schema.column(i).converted_type();
+                    metadata
+                        .row_groups()
+                        .iter()
+                        .map(|row_group| {
+                            let (min, max) =
+                                stat_bounds(row_group.column(i).statistics()?, converted)?;
+                            Some((min.as_f64()?, max.as_f64()?))
+                        })
+                        .reduce(|a, b| Some((a?.0.min(b?.0), a?.1.max(b?.1))))?
+                });
+                ColumnStats {
+                    distinct: 0,
+                    nulls: nulls.unwrap_or(0) as usize,
+                    min: bounds.map(|(min, _)| min),
+                    max: bounds.map(|(_, max)| max),
+                }
+            })
+            .collect();
+
+        Ok(TableStats {
+            rows: metadata.file_metadata().num_rows() as usize,
+            columns,
+        })
+    }
+
+    /// Reads the data, taking the row groups already in `cache` from it and
+    /// adding the ones it decodes.
+    pub fn finish(self, cache: &mut RowGroupCache) -> PolarsResult<DataFrame> {
+        let options = ArrowReaderOptions::new().with_skip_arrow_metadata(true);
+        let arrow_metadata =
+            ArrowReaderMetadata::load(&self.reader, options).map_err(to_polars_err)?;
+        let metadata = arrow_metadata.metadata().clone();
+        let schema = metadata.file_metadata().schema_descr();
+        let root = schema.root_schema();
+
+        // Row groups to read, with their rows as positions in the file.
+        let mut remaining = self.limit.unwrap_or(usize::MAX);
+        let mut start = 0;
+        let mut row_groups = Vec::new();
+        for (i, row_group) in metadata.row_groups().iter().enumerate() {
+            let rows = row_group.num_rows() as usize;
+            if remaining > 0
+                && self
+                    .filters
+                    .iter()
+                    .all(|filter| row_group_may_match(row_group, filter))
+            {
+                remaining = remaining.saturating_sub(rows);
+                row_groups.push((i, start..start + rows));
+            }
+            start += rows;
+        }
+
+        let wanted: HashSet<&str> = match &self.columns {
+            Some(columns) => columns.iter().map(String::as_str).collect(),
+            None => root.get_fields().iter().map(|f| f.name()).collect(),
+        };
+        let projected = root
+            .get_fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| wanted.contains(f.name()))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let mut data: Option<DataFrame> = None;
+        if !projected.is_empty() {
+            if cache.projected != projected {
+                *cache = RowGroupCache {
+                    projected: projected.clone(),
+                    row_groups: HashMap::new(),
+                };
+            }
+            let projection = ProjectionMask::roots(schema, projected);
+            // One row group at a time, so only its column chunks are held in
+            // memory besides the values read so far.
+            for (i, _) in &row_groups {
+                if let Some(df) = cache.row_groups.get(i) {
+                    match &mut data {
+                        Some(data) => {
+                            data.vstack_mut(df)?;
+                        }
+                        None => data = Some(df.clone()),
+                    }
+                    continue;
+                }
+                let ranges = metadata
+                    .row_group(*i)
+                    .columns()
+                    .iter()
+                    .filter(|col| {
+                        col.column_path()
+                            .parts()
+                            .first()
+                            .is_some_and(|name| wanted.contains(name.as_str()))
+                    })
+                    .map(|col| col.byte_range())
+                    .collect::<Vec<_>>();
+                self.reader.prefetch(ranges).map_err(to_polars_err)?;
+                let batches = ParquetRecordBatchReaderBuilder::new_with_metadata(
+                    self.reader.clone(),
+                    arrow_metadata.clone(),
+                )
+                .with_row_groups(vec![*i])
+                .with_projection(projection.clone())
+                .build()
+                .map_err(to_polars_err)?;
+                let df = batches_to_df(batches)?;
+                self.reader.release();
+                match &mut data {
+                    Some(data) => {
+                        data.vstack_mut(&df)?;
+                    }
+                    None => data = Some(df.clone()),
+                }
+                cache.row_groups.insert(*i, df);
+            }
+        }
+        if let Some(data) = &mut data {
+            data.as_single_chunk_par();
+        }
+
+        let height = row_groups.iter().map(|(_, rows)| rows.len()).sum();
+        let mut columns = root
+            .get_fields()
+            .iter()
+            .map(|field| {
+                let dtype = field_dtype(field);
+                match data
+                    .as_ref()
+                    .and_then(|data| data.column(field.name()).ok())
+                {
+                    Some(column) => to_declared(column.clone(), &dtype),
+                    None => Ok(Column::full_null(field.name().into(), height, &dtype)),
+                }
+            })
+            .collect::<PolarsResult<Vec<_>>>()?;
+        if let Some(name) = &self.row_index {
+            let index = row_groups
+                .iter()
+                .flat_map(|(_, rows)| rows.clone().map(|i| i as IdxSize))
+                .collect::<Vec<_>>();
+            columns.push(Column::new(name.into(), index));
+        }
+
+        DataFrame::new(columns)
+    }
+}
+
+/// Hands the record batches decoded by the arrow reader over to Polars,
+/// through an IPC stream. Types Polars does not read are cast first.
+fn batches_to_df(batches: ParquetRecordBatchReader) -> PolarsResult<DataFrame> {
+    let fields = batches
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| ArrowField::new(field.name(), readable_type(field.data_type()), true))
+        .collect::<Vec<_>>();
+    let schema = Arc::new(ArrowSchema::new(fields));
+
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema).map_err(to_polars_err)?;
+    for batch in batches {
+        let batch = batch.map_err(to_polars_err)?;
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(schema.fields())
+            .map(|(column, field)| cast(column, field.data_type()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(to_polars_err)?;
+        let batch = RecordBatch::try_new(schema.clone(), columns).map_err(to_polars_err)?;
+        writer.write(&batch).map_err(to_polars_err)?;
+    }
+    let ipc = writer.into_inner().map_err(to_polars_err)?;
+    IpcStreamReader::new(Cursor::new(ipc)).finish()
+}
+
+/// Type a column is cast to before Polars reads it: times as the integers
+/// they are stored as, timestamps without their time zone, half floats as
+/// floats, decimals as text.
+fn readable_type(dtype: &ArrowDataType) -> ArrowDataType {
+    match dtype {
+        ArrowDataType::Time32(_) => ArrowDataType::Int32,
+        ArrowDataType::Time64(_) => ArrowDataType::Int64,
+        ArrowDataType::Timestamp(unit, Some(_)) => ArrowDataType::Timestamp(*unit, None),
+        ArrowDataType::Float16 => ArrowDataType::Float32,
+        ArrowDataType::FixedSizeBinary(_) => ArrowDataType::Binary,
+        ArrowDataType::Decimal128(..) | ArrowDataType::Decimal256(..) => ArrowDataType::Utf8,
+        dtype => dtype.clone(),
+    }
+}
+
+/// Converts `column` to the type `schema` declares for it. Text is declared
+/// for nested values and decimals, which are rendered with
+/// [`values_to_text`].
+fn to_declared(column: Column, declared: &DataType) -> PolarsResult<Column> {
+    match declared {
+        DataType::String if column.dtype() != declared => values_to_text(column),
+        declared => column.cast(declared),
+    }
+}
+
+fn to_polars_err<E: std::fmt::Display>(err: E) -> PolarsError {
+    PolarsError::ComputeError(format!("Parquet range read error: {}", err).into())
+}
+
+/// Polars type of a top level Parquet field as [`ParquetRangeReader`]
+/// returns it. Nested fields are rendered as text.
+fn field_dtype(field: &Type) -> DataType {
+    let info = field.get_basic_info();
+    if !field.is_primitive() || (info.has_repetition() && info.repetition() == Repetition::REPEATED)
+    {
+        return DataType::String;
+    }
+
+    match (field.get_physical_type(), info.converted_type()) {
+        (PhysicalType::BOOLEAN, _) => DataType::Boolean,
+        (PhysicalType::INT32, ConvertedType::INT_8) => DataType::Int8,
+        (PhysicalType::INT32, ConvertedType::INT_16) => DataType::Int16,
+        (PhysicalType::INT32, ConvertedType::UINT_8) => DataType::UInt8,
+        (PhysicalType::INT32, ConvertedType::UINT_16) => DataType::UInt16,
+        (PhysicalType::INT32, ConvertedType::UINT_32) => DataType::UInt32,
+        (PhysicalType::INT32, ConvertedType::DATE) => DataType::Date,
+        (PhysicalType::INT64, ConvertedType::UINT_64) => DataType::UInt64,
+        (PhysicalType::INT64, ConvertedType::TIMESTAMP_MILLIS) | (PhysicalType::INT96, _) => {
+            DataType::Datetime(TimeUnit::Milliseconds, None)
+        }
+        (PhysicalType::INT64, ConvertedType::TIMESTAMP_MICROS) => {
+            DataType::Datetime(TimeUnit::Microseconds, None)
+        }
+        (_, ConvertedType::DECIMAL) => DataType::String,
+        (PhysicalType::INT32, _) => DataType::Int32,
+        (PhysicalType::INT64, _) => DataType::Int64,
+        (PhysicalType::FLOAT, _) => DataType::Float32,
+        (PhysicalType::DOUBLE, _) => DataType::Float64,
+        (
+            PhysicalType::BYTE_ARRAY,
+            ConvertedType::UTF8 | ConvertedType::ENUM | ConvertedType::JSON,
+        ) => DataType::String,
+        (PhysicalType::FIXED_LEN_BYTE_ARRAY, _)
+            if info.logical_type() == Some(LogicalType::Float16) =>
+        {
+            DataType::Float32
+        }
+        (PhysicalType::BYTE_ARRAY | PhysicalType::FIXED_LEN_BYTE_ARRAY, _) => DataType::Binary,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum StatValue {
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+impl StatValue {
+    fn from_any(value: &AnyValue) -> Option<Self> {
+        match value {
+            AnyValue::Int64(i) => Some(Self::Int(*i)),
+            AnyValue::Int32(i) => Some(Self::Int(*i as i64)),
+            AnyValue::Float64(f) => Some(Self::Float(*f)),
+            AnyValue::Float32(f) => Some(Self::Float(*f as f64)),
+            AnyValue::String(s) => Some(Self::Bytes(s.as_bytes().to_vec())),
+            AnyValue::StringOwned(s) => Some(Self::Bytes(s.as_bytes().to_vec())),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            Self::Bytes(_) => None,
+        }
+    }
+
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => Some(a.cmp(b)),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            (Self::Int(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
+            (Self::Float(a), Self::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Self::Bytes(a), Self::Bytes(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// Min and max of a column chunk, for the types whose statistics order
+/// matches the order of the values read back.
+fn stat_bounds(stats: &Statistics, converted: ConvertedType) -> Option<(StatValue, StatValue)> {
+    if stats.is_min_max_deprecated() {
+        return None;
+    }
+    match (stats, converted) {
+        (Statistics::Int32(s), ConvertedType::NONE | ConvertedType::INT_32) => Some((
+            StatValue::Int(*s.min_opt()? as i64),
+            StatValue::Int(*s.max_opt()? as i64),
+        )),
+        (Statistics::Int64(s), ConvertedType::NONE | ConvertedType::INT_64) => {
+            Some((StatValue::Int(*s.min_opt()?), StatValue::Int(*s.max_opt()?)))
+        }
+        (Statistics::Float(s), _) => Some((
+            StatValue::Float(*s.min_opt()? as f64),
+            StatValue::Float(*s.max_opt()? as f64),
+        )),
+        (Statistics::Double(s), _) => Some((
+            StatValue::Float(*s.min_opt()?),
+            StatValue::Float(*s.max_opt()?),
+        )),
+        (Statistics::ByteArray(s), ConvertedType::UTF8) => Some((
+            StatValue::Bytes(s.min_opt()?.data().to_vec()),
+            StatValue::Bytes(s.max_opt()?.data().to_vec()),
+        )),
+        _ => None,
+    }
+}
+
+/// `false` only when the statistics prove no row of the range can satisfy
+/// `column <op> value`.
+fn range_may_match(min: &StatValue, max: &StatValue, op: PlanOp, value: &StatValue) -> bool {
+    let (vs_min, vs_max) = match (value.compare(min), value.compare(max)) {
+        (Some(vs_min), Some(vs_max)) => (vs_min, vs_max),
+        _ => return true,
+    };
+    match op {
+        PlanOp::EQ => vs_min != Ordering::Less && vs_max != Ordering::Greater,
+        PlanOp::GT => vs_max == Ordering::Less,
+        PlanOp::GE => vs_max != Ordering::Greater,
+        PlanOp::LT => vs_min == Ordering::Greater,
+        PlanOp::LE => vs_min != Ordering::Less,
+        _ => true,
+    }
+}
+
+fn row_group_may_match(row_group: &RowGroupMetaData, filter: &RowGroupFilter) -> bool {
+    let schema = row_group.schema_descr();
+    let leaf = (0..schema.num_columns()).find(|i| {
+        let column = schema.column(*i);
+        column.path().parts() == [filter.column.as_str()]
+    });
+    let i = match leaf {
+        Some(i) => i,
+        None => return true,
+    };
+    let stats = row_group.column(i).statistics();
+
+    if let PlanOp::ISNULL | PlanOp::ISNOTNULL = filter.op {
+        let rows = row_group.num_rows() as u64;
+        return stats
+            .and_then(|stats| stats.null_count_opt())
+            .is_none_or(|nulls| match filter.op {
+                PlanOp::ISNULL => nulls > 0,
+                _ => nulls < rows,
+            });
+    }
+
+    let value = match StatValue::from_any(&filter.value) {
+        Some(value) => value,
+        None => return true,
+    };
+    let converted = schema.column(i).converted_type();
+    stats
+        .and_then(|stats| stat_bounds(stats, converted))
+        .is_none_or(|(min, max)| range_may_match(&min, &max, filter.op, &value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::tests::serve_with;
+    use polars::prelude::{col, lit, NamedFrom, ParquetCompression, ParquetWriter, Series};
+
+    fn write_parquet(name: &str, df: &mut DataFrame, row_group_size: usize) -> DataSource {
+        let path = std::env::temp_dir().join(name);
+        let file = File::create(&path).unwrap();
+        ParquetWriter::new(file)
+            .with_row_group_size(Some(row_group_size))
+            .finish(df)
+            .unwrap();
+        DataSource::FILE(path)
+    }
+
+    /// Serves `df` as an uncompressed Parquet file, recording the `Range` of
+    /// every request. Without `ranges` the server ignores them and sends the
+    /// whole file; `etag` gives the ETag of the n-th response.
+    fn serve_parquet(
+        df: &mut DataFrame,
+        row_group_size: usize,
+        ranges: bool,
+        etag: fn(usize) -> &'static str,
+    ) -> (DataSource, Arc<Mutex<Vec<String>>>) {
+        let mut bytes = Vec::new();
+        ParquetWriter::new(&mut bytes)
+            .with_compression(ParquetCompression::Uncompressed)
+            .with_row_group_size(Some(row_group_size))
+            .finish(df)
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+
+        let url = serve_with(move |request| {
+            let range = request
+                .lines()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("range: bytes=")
+                        .map(String::from)
+                })
+                .unwrap_or_default();
+            let mut log = log.lock().unwrap();
+            log.push(range.clone());
+            let total = bytes.len();
+            let (start, end) = match range.split_once('-') {
+                _ if !ranges => (0, total),
+                Some(("", suffix)) => (total.saturating_sub(suffix.parse().unwrap()), total),
+                Some((start, end)) => (
+                    start.parse().unwrap(),
+                    total.min(end.parse::<usize>().unwrap() + 1),
+                ),
+                None => (0, total),
+            };
+            let status = match ranges {
+                true => format!(
+                    "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                    start,
+                    end - 1,
+                    total
+                ),
+                false => "200 OK".to_string(),
+            };
+            let mut response = format!(
+                "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                etag(log.len()),
+                end - start
+            )
+            .into_bytes();
+            response.extend_from_slice(&bytes[start..end]);
+            response
+        });
+        (DataSource::HTTP(url), requests)
+    }
+
+    /// Large enough for the row groups to lie outside the footer prefetch.
+    fn numbers() -> DataFrame {
+        let ids = (0..20_000i64).collect::<Vec<_>>();
+        let names = ids
+            .iter()
+            .map(|i| format!("name {}", i))
+            .collect::<Vec<_>>();
+        DataFrame::new(vec![
+            Column::new("id".into(), ids),
+            Column::new("name".into(), names),
+        ])
+        .unwrap()
+    }
+
+    fn cities() -> DataFrame {
+        DataFrame::new(vec![
+            Column::new("name".into(), ["Chicago", "Dallas", "Houston", "Phoenix"]),
+            Column::new("pop".into(), [2705627i64, 1219399, 2129784, 1465114]),
+            Column::new("lat".into(), [41.87, 32.77, 29.75, 33.44]),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scan_local() {
+        let source = write_parquet("sqlite_httpfs_test_scan.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let schema = ParquetRangeReader::new(reader.clone()).schema().unwrap();
+        let df = scan_local(reader.local_path().unwrap(), "index", &schema)
+            .unwrap()
+            .finish(vec![col("pop").lt(lit(2000000i64))])
+            .unwrap()
+            .collect()
+            .unwrap();
+
+        let names = df.column("name").unwrap().str().unwrap();
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            [Some("Dallas"), Some("Phoenix")]
+        );
+        let index = df.column("index").unwrap().idx().unwrap();
+        assert_eq!(index.into_iter().collect::<Vec<_>>(), [Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn test_scan_local_declared_types() {
+        let tags = [
+            Series::new("".into(), ["a", "b"]),
+            Series::new("".into(), ["c"]),
+        ];
+        let mut df = DataFrame::new(vec![
+            Column::new("id".into(), [1i64, 2]),
+            Column::new("tags".into(), tags),
+        ])
+        .unwrap();
+        let source = write_parquet("sqlite_httpfs_test_scan_types.parquet", &mut df, 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let reader_path = reader.local_path().unwrap().to_path_buf();
+        let schema = ParquetRangeReader::new(reader.clone()).schema().unwrap();
+        let df = scan_local(reader.local_path().unwrap(), "index", &schema)
+            .unwrap()
+            .finish(Vec::new())
+            .unwrap()
+            .collect()
+            .unwrap();
+
+        assert_eq!(schema.get("tags"), Some(&DataType::String));
+        assert_eq!(df.column("id").unwrap().dtype(), &DataType::Int64);
+        // The same text as a read through the range reader.
+        let remote = ParquetRangeReader::new(reader)
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+        assert_eq!(df.column("tags").unwrap(), remote.column("tags").unwrap());
+        assert_eq!(
+            df.column("tags").unwrap().str().unwrap().get(0),
+            Some(r#"["a", "b"]"#)
+        );
+
+        // Filtered and projected as a query would.
+        let scan = scan_local(reader_path.as_path(), "index", &schema).unwrap();
+        let predicate = scan.column("tags").str().contains_literal(lit("c"));
+        let lf = scan
+            .finish(vec![predicate])
+            .unwrap()
+            .select([col("tags"), col("index")]);
+        // The predicate on the converted column is evaluated by the scan.
+        let plan = lf.describe_optimized_plan().unwrap();
+        let (_, scan) = plan.split_once("Parquet SCAN").unwrap();
+        assert!(scan.contains("SELECTION"));
+        let df = lf.collect().unwrap();
+        assert_eq!(
+            df.column("tags").unwrap().str().unwrap().get(0),
+            Some(r#"["c"]"#)
+        );
+        assert_eq!(df.column("index").unwrap().idx().unwrap().get(0), Some(1));
+    }
+
+    #[test]
+    fn test_range_cache() {
+        let mut cache = RangeCache {
+            footer: Some((90, Bytes::from_static(b"0123456789"))),
+            chunks: vec![(10, Bytes::from_static(b"abcdef"))],
+        };
+        assert_eq!(cache.get(12, 2), Some(Bytes::from_static(b"cdef")));
+        assert_eq!(cache.get(95, 5), Some(Bytes::from_static(b"56789")));
+        assert_eq!(cache.get(14, 4), None);
+        cache.chunks.clear();
+        assert_eq!(cache.get(12, 2), None);
+        assert!(cache.get(90, 1).is_some());
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        assert_eq!(
+            coalesce_ranges(vec![(100, 10), (0, 10), (15, 5)], 5),
+            vec![(0, 20), (100, 110)]
+        );
+        assert_eq!(coalesce_ranges(vec![], 5), vec![]);
+    }
+
+    #[test]
+    fn test_range_may_match() {
+        let (min, max) = (StatValue::Int(10), StatValue::Int(20));
+        assert!(range_may_match(&min, &max, PlanOp::EQ, &StatValue::Int(15)));
+        assert!(!range_may_match(
+            &min,
+            &max,
+            PlanOp::EQ,
+            &StatValue::Int(21)
+        ));
+        assert!(!range_may_match(
+            &min,
+            &max,
+            PlanOp::GT,
+            &StatValue::Int(20)
+        ));
+        assert!(range_may_match(&min, &max, PlanOp::GE, &StatValue::Int(20)));
+        assert!(!range_may_match(
+            &min,
+            &max,
+            PlanOp::LT,
+            &StatValue::Int(10)
+        ));
+        assert!(range_may_match(&min, &max, PlanOp::LE, &StatValue::Int(10)));
+        assert!(range_may_match(&min, &max, PlanOp::NE, &StatValue::Int(15)));
+        // Fractional values are compared as they are, not truncated.
+        assert!(!range_may_match(
+            &min,
+            &max,
+            PlanOp::EQ,
+            &StatValue::Float(1.0)
+        ));
+        assert!(range_may_match(
+            &min,
+            &max,
+            PlanOp::LT,
+            &StatValue::Float(10.5)
+        ));
+        assert!(!range_may_match(
+            &min,
+            &max,
+            PlanOp::GT,
+            &StatValue::Float(20.0)
+        ));
+        assert!(range_may_match(
+            &min,
+            &max,
+            PlanOp::GT,
+            &StatValue::Float(19.5)
+        ));
+        // Values of another kind never prune.
+        assert!(range_may_match(
+            &min,
+            &max,
+            PlanOp::EQ,
+            &StatValue::Bytes(b"15".to_vec())
+        ));
+    }
+
+    #[test]
+    fn test_parquet_range_reader_schema() {
+        let source = write_parquet("sqlite_httpfs_test_schema.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let schema = ParquetRangeReader::new(reader).schema().unwrap();
+
+        assert_eq!(schema.get("name"), Some(&DataType::String));
+        assert_eq!(schema.get("pop"), Some(&DataType::Int64));
+        assert_eq!(schema.get("lat"), Some(&DataType::Float64));
+    }
+
+    #[test]
+    fn test_parquet_range_reader_full() {
+        let source = write_parquet("sqlite_httpfs_test_full.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df, cities());
+    }
+
+    #[test]
+    fn test_parquet_range_reader_columns_and_filters() {
+        let source = write_parquet("sqlite_httpfs_test_filters.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .with_columns(vec!["name".to_string()])
+            .with_filters(vec![RowGroupFilter {
+                column: "name".to_string(),
+                op: PlanOp::GT,
+                value: AnyValue::StringOwned("Dallas".into()),
+            }])
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df.shape(), (2, 3));
+        let names = df.column("name").unwrap().str().unwrap();
+        assert_eq!(names.get(0), Some("Houston"));
+        assert_eq!(names.get(1), Some("Phoenix"));
+        assert_eq!(df.column("pop").unwrap().null_count(), 2);
+        assert_eq!(df.column("pop").unwrap().dtype(), &DataType::Int64,);
+    }
+
+    #[test]
+    fn test_parquet_range_reader_no_columns() {
+        let source = write_parquet("sqlite_httpfs_test_count.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .with_columns(Vec::new())
+            .with_filters(vec![RowGroupFilter {
+                column: "pop".to_string(),
+                op: PlanOp::LT,
+                value: AnyValue::Int64(1_300_000),
+            }])
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df.height(), 2);
+    }
+
+    #[test]
+    fn test_parquet_range_reader_limit() {
+        let source = write_parquet("sqlite_httpfs_test_limit.parquet", &mut cities(), 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .with_limit(1)
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df, cities().head(Some(2)));
+    }
+
+    #[test]
+    fn test_parquet_range_reader_null_filters() {
+        let mut df = DataFrame::new(vec![Column::new(
+            "state".into(),
+            [Some("IL"), Some("TX"), None, None],
+        )])
+        .unwrap();
+        let source = write_parquet("sqlite_httpfs_test_nulls.parquet", &mut df, 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+
+        for (op, expected, first_row) in [
+            (PlanOp::ISNULL, None, 2),
+            (PlanOp::ISNOTNULL, Some("IL"), 0),
+        ] {
+            let df = ParquetRangeReader::new(reader.clone())
+                .with_filters(vec![RowGroupFilter {
+                    column: "state".to_string(),
+                    op,
+                    value: AnyValue::Null,
+                }])
+                .with_row_index("index".to_string())
+                .finish(&mut RowGroupCache::default())
+                .unwrap();
+            assert_eq!(df.height(), 2);
+            assert_eq!(df.column("state").unwrap().str().unwrap().get(0), expected);
+            let index = df.column("index").unwrap().idx().unwrap();
+            assert_eq!(index.get(0), Some(first_row));
+            assert_eq!(index.get(1), Some(first_row + 1));
+        }
+    }
+
+    #[test]
+    fn test_range_reader_http_footer_prefetch() {
+        let (source, requests) = serve_parquet(&mut numbers(), 5_000, true, |_| "\"v1\"");
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let schema = ParquetRangeReader::new(reader.clone()).schema().unwrap();
+        let stats = ParquetRangeReader::new(reader).stats().unwrap();
+
+        assert_eq!(schema.get("id"), Some(&DataType::Int64));
+        assert_eq!(stats.rows, 20_000);
+        assert_eq!(*requests.lock().unwrap(), [format!("-{}", FOOTER_PREFETCH)]);
+    }
+
+    #[test]
+    fn test_range_reader_http_coalesced_ranges() {
+        let (source, requests) = serve_parquet(&mut numbers(), 5_000, true, |_| "\"v1\"");
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .with_filters(vec![RowGroupFilter {
+                column: "id".to_string(),
+                op: PlanOp::LT,
+                value: AnyValue::Int64(100),
+            }])
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df, numbers().head(Some(5_000)));
+        // The footer, then both column chunks of the first row group at once.
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("4-"));
+    }
+
+    #[test]
+    fn test_parquet_range_reader_cached_row_groups() {
+        let (source, requests) = serve_parquet(&mut numbers(), 5_000, true, |_| "\"v1\"");
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let mut cache = RowGroupCache::default();
+        let read = |cache: &mut RowGroupCache, id: i64| {
+            ParquetRangeReader::new(reader.clone())
+                .with_filters(vec![RowGroupFilter {
+                    column: "id".to_string(),
+                    op: PlanOp::EQ,
+                    value: AnyValue::Int64(id),
+                }])
+                .finish(cache)
+                .unwrap()
+        };
+
+        assert_eq!(read(&mut cache, 10), numbers().head(Some(5_000)));
+        assert_eq!(read(&mut cache, 20), numbers().head(Some(5_000)));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(read(&mut cache, 5_010), numbers().slice(5_000, 5_000));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_range_reader_http_range_ignored() {
+        let (source, requests) = serve_parquet(&mut numbers(), 5_000, false, |_| "\"v1\"");
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let df = ParquetRangeReader::new(reader)
+            .finish(&mut RowGroupCache::default())
+            .unwrap();
+
+        assert_eq!(df, numbers());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_range_reader_http_etag_changed() {
+        let (source, _) = serve_parquet(&mut numbers(), 5_000, true, |n| match n {
+            1 => "\"v1\"",
+            _ => "\"v2\"",
+        });
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let err = ParquetRangeReader::new(reader)
+            .finish(&mut RowGroupCache::default())
+            .unwrap_err();
+
+        assert!(err.to_string().contains("changed while it was read"));
+    }
+
+    #[test]
+    fn test_parquet_range_reader_stats() {
+        let mut df = DataFrame::new(vec![Column::new(
+            "state".into(),
+            [Some("IL"), Some("TX"), None, None],
+        )])
+        .unwrap();
+        let source = write_parquet("sqlite_httpfs_test_stats.parquet", &mut df, 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let stats = ParquetRangeReader::new(reader).stats().unwrap();
+
+        assert_eq!(stats.rows, 4);
+        assert_eq!(stats.columns[0].nulls, 2);
+        assert_eq!(stats.columns[0].min, None);
+
+        let mut df = cities();
+        let source = write_parquet("sqlite_httpfs_test_stats_bounds.parquet", &mut df, 2);
+        let reader = RangeReader::new(source, FetchOpts::default()).unwrap();
+        let stats = ParquetRangeReader::new(reader).stats().unwrap();
+        assert_eq!(stats.columns[1].min, Some(1219399.0));
+        assert_eq!(stats.columns[1].max, Some(2705627.0));
+        assert_eq!(stats.columns[2].min, Some(29.75));
+        assert_eq!(stats.columns[0].max, None);
+    }
+}
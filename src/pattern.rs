@@ -0,0 +1,131 @@
+/// Translates a `LIKE` pattern into an anchored regex with SQLite semantics:
+/// `%` matches any sequence, `_` a single character, and only ASCII letters
+/// compare case-insensitively.
+pub fn like_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("(?s)^");
+    for c in pattern.chars() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c if c.is_ascii_alphabetic() => {
+                regex.push('[');
+                regex.push(c.to_ascii_lowercase());
+                regex.push(c.to_ascii_uppercase());
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Translates a `GLOB` pattern into an anchored, case-sensitive regex: `*`
+/// matches any sequence, `?` a single character and `[...]` a character
+/// class, negated by a leading `^`. Patterns SQLite never matches, like an
+/// unterminated class, return `None`.
+pub fn glob_to_regex(pattern: &str) -> Option<String> {
+    let mut regex = String::from("(?s)^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                let negated = chars.next_if_eq(&'^').is_some();
+                // A `]` right after the opening bracket is a member.
+                let mut first = true;
+                loop {
+                    let c = chars.next()?;
+                    if c == ']' && !first {
+                        break;
+                    }
+                    first = false;
+                    match chars.peek() {
+                        Some('-') => {
+                            chars.next();
+                            match chars.peek() {
+                                Some(']') | None => {
+                                    class.push_str(&class_escape(c));
+                                    class.push_str(&class_escape('-'));
+                                }
+                                Some(&end) => {
+                                    chars.next();
+                                    if c <= end {
+                                        class.push_str(&class_escape(c));
+                                        class.push('-');
+                                        class.push_str(&class_escape(end));
+                                    }
+                                }
+                            }
+                        }
+                        _ => class.push_str(&class_escape(c)),
+                    }
+                }
+                match (class.is_empty(), negated) {
+                    (true, false) => return None,
+                    (true, true) => regex.push('.'),
+                    (false, false) => regex.push_str(&format!("[{}]", class)),
+                    (false, true) => regex.push_str(&format!("[^{}]", class)),
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Some(regex)
+}
+
+fn class_escape(c: char) -> String {
+    if c.is_alphanumeric() {
+        c.to_string()
+    } else {
+        format!("\\{}", c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn like(pattern: &str, value: &str) -> bool {
+        Regex::new(&like_to_regex(pattern)).unwrap().is_match(value)
+    }
+
+    fn glob(pattern: &str, value: &str) -> bool {
+        glob_to_regex(pattern)
+            .map(|regex| Regex::new(&regex).unwrap().is_match(value))
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_like() {
+        assert!(like("San %", "San Diego"));
+        assert!(like("san %", "SAN JOSE"));
+        assert!(!like("San %", "Santa Ana"));
+        assert!(like("_allas", "Dallas"));
+        assert!(like("%.csv", "a.csv"));
+        assert!(!like("%.csv", "a_csv"));
+        assert!(like("100%", "100% sure"));
+        // Only ASCII letters fold case, like SQLite's built-in LIKE.
+        assert!(!like("ä%", "Äpfel"));
+        assert!(like("%\n%", "a\nb"));
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("San *", "San Diego"));
+        assert!(!glob("san *", "San Diego"));
+        assert!(glob("?allas", "Dallas"));
+        assert!(glob("[A-C]*", "Chicago"));
+        assert!(!glob("[^A-C]*", "Chicago"));
+        assert!(glob("[]x]", "]"));
+        assert!(glob("[a-]", "-"));
+        assert!(glob("a.b", "a.b"));
+        assert!(!glob("a.b", "axb"));
+        assert!(!glob("[abc", "a"));
+        assert!(!glob("[z-a]", "m"));
+    }
+}
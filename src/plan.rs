@@ -0,0 +1,166 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use sqlite_loadable::table::ConstraintOperator;
+
+/// Operator of a constraint evaluated by Polars.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlanOp {
+    EQ,
+    /// An `IN` list, its values read all at once.
+    IN,
+    NE,
+    GT,
+    GE,
+    LT,
+    LE,
+    ISNULL,
+    ISNOTNULL,
+    LIKE,
+    GLOB,
+}
+
+impl PlanOp {
+    /// Maps the operators `filter` knows how to evaluate. `IN` lists arrive
+    /// as `EQ` and are told apart by `best_index`.
+    pub fn from_operator(op: &ConstraintOperator) -> Option<PlanOp> {
+        match op {
+            ConstraintOperator::EQ => Some(PlanOp::EQ),
+            ConstraintOperator::NE => Some(PlanOp::NE),
+            ConstraintOperator::GT => Some(PlanOp::GT),
+            ConstraintOperator::GE => Some(PlanOp::GE),
+            ConstraintOperator::LT => Some(PlanOp::LT),
+            ConstraintOperator::LE => Some(PlanOp::LE),
+            ConstraintOperator::ISNULL => Some(PlanOp::ISNULL),
+            ConstraintOperator::ISNOTNULL => Some(PlanOp::ISNOTNULL),
+            ConstraintOperator::LIKE => Some(PlanOp::LIKE),
+            ConstraintOperator::GLOB => Some(PlanOp::GLOB),
+            _ => None,
+        }
+    }
+
+    /// Whether the collating sequence of the constraint decides its result.
+    pub fn compares(&self) -> bool {
+        matches!(
+            self,
+            PlanOp::EQ
+                | PlanOp::IN
+                | PlanOp::NE
+                | PlanOp::GT
+                | PlanOp::GE
+                | PlanOp::LT
+                | PlanOp::LE
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanConstraint {
+    pub column: usize,
+    pub op: PlanOp,
+    /// 1-based position of the right-hand value in the `filter` arguments.
+    pub argv_index: usize,
+    /// Collating sequence SQLite compares with, `BINARY` unless the query
+    /// asks for another one.
+    pub collation: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OrderTerm {
+    pub column: usize,
+    pub descending: bool,
+}
+
+/// What `best_index` agreed to do for a query, passed to `filter` as the
+/// `idx_str` of the chosen plan.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub constraints: Vec<PlanConstraint>,
+    /// Columns read by the query, in table order.
+    pub columns: Vec<usize>,
+    /// Sort consumed from the `ORDER BY` clause, empty when SQLite sorts.
+    pub order: Vec<OrderTerm>,
+    /// Argument positions of the `LIMIT` and `OFFSET` values.
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl QueryPlan {
+    pub fn serialize(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn deserialize(idx_str: &str) -> Result<QueryPlan, Box<dyn Error>> {
+        serde_json::from_str(idx_str).map_err(|e| format!("Invalid query plan: {}", e).into())
+    }
+
+    /// Number of `filter` arguments the plan asks SQLite for.
+    pub fn argc(&self) -> usize {
+        self.constraints.len() + self.limit.iter().count() + self.offset.iter().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ops = [
+            PlanOp::EQ,
+            PlanOp::IN,
+            PlanOp::NE,
+            PlanOp::GT,
+            PlanOp::GE,
+            PlanOp::LT,
+            PlanOp::LE,
+            PlanOp::ISNULL,
+            PlanOp::ISNOTNULL,
+            PlanOp::LIKE,
+            PlanOp::GLOB,
+        ];
+        let plan = QueryPlan {
+            constraints: ops
+                .iter()
+                .enumerate()
+                .map(|(i, op)| PlanConstraint {
+                    column: 10 + i,
+                    op: *op,
+                    argv_index: i + 1,
+                    collation: "BINARY".to_string(),
+                })
+                .collect(),
+            columns: vec![0, 2, 3],
+            order: vec![OrderTerm {
+                column: 2,
+                descending: true,
+            }],
+            limit: Some(12),
+            offset: Some(13),
+        };
+
+        let idx_str = plan.serialize().unwrap();
+        assert_eq!(QueryPlan::deserialize(&idx_str).unwrap(), plan);
+        assert_eq!(plan.argc(), 13);
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        let err = QueryPlan::deserialize("3>,1=|0,2").unwrap_err();
+        assert!(err.to_string().starts_with("Invalid query plan"));
+    }
+
+    #[test]
+    fn test_from_operator() {
+        assert_eq!(
+            PlanOp::from_operator(&ConstraintOperator::NE),
+            Some(PlanOp::NE)
+        );
+        assert_eq!(PlanOp::from_operator(&ConstraintOperator::LIMIT), None);
+        assert_eq!(PlanOp::from_operator(&ConstraintOperator::REGEXP), None);
+        assert_eq!(PlanOp::from_operator(&ConstraintOperator::MATCH), None);
+        assert!(PlanOp::IN.compares());
+        assert!(!PlanOp::LIKE.compares());
+    }
+}
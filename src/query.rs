@@ -0,0 +1,276 @@
+use std::{collections::HashMap, error::Error as StdError, mem, os::raw::c_int};
+
+use polars::{prelude::*, sql::SQLContext};
+use sqlite_loadable::{
+    api,
+    prelude::*,
+    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Error, Result,
+};
+
+use crate::{args::split_options, result_value, storage::StorageOpts, TableOpts, UrlTable};
+
+/// Result columns of `httpfs_query`. The schema of a table-valued function is
+/// declared before any query runs, so the columns are named by position.
+const RESULT_COLUMNS: usize = 16;
+/// Hidden columns receiving the arguments, in call order.
+const ARGUMENTS: [&str; 4] = ["url", "format", "query", "options"];
+/// Name of the fetched data in the SQL statement.
+const TABLE_NAME: &str = "t";
+
+/// `httpfs_query(url, format, sql[, options])`: runs a Polars SQL statement
+/// over the data at `url`, exposed as the table `t`, and returns its rows.
+/// `options` takes the options of `CREATE VIRTUAL TABLE`, comma separated.
+#[repr(C)]
+pub struct QueryTable {
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for QueryTable {
+    type Aux = ();
+    type Cursor = QueryCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, Self)> {
+        let columns = (0..RESULT_COLUMNS)
+            .map(|i| format!("c{}", i))
+            .chain(ARGUMENTS.iter().map(|name| format!("{} HIDDEN", name)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((format!("CREATE TABLE x({});", columns), QueryTable { base }))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        // Bit i of idx_num is set when the i-th argument was given, the
+        // values follow in the same order.
+        let mut constraints = info.constraints();
+        let mut arguments = Vec::new();
+        for constraint in constraints.iter_mut() {
+            let Some(argument) = (constraint.column_idx() as usize).checked_sub(RESULT_COLUMNS)
+            else {
+                continue;
+            };
+            if argument >= ARGUMENTS.len() || constraint.op() != Some(ConstraintOperator::EQ) {
+                continue;
+            }
+            if !constraint.usable() {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_omit(true);
+            arguments.push((argument, constraint));
+        }
+        arguments.sort_by_key(|(argument, _)| *argument);
+
+        let mut idx_num = 0;
+        for (argv_index, (argument, constraint)) in arguments.iter_mut().enumerate() {
+            constraint.set_argv_index(argv_index as i32 + 1);
+            idx_num |= 1 << *argument;
+        }
+        info.set_idxnum(idx_num);
+        info.set_estimated_cost(1_000_000.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<QueryCursor> {
+        Ok(QueryCursor::new())
+    }
+}
+
+#[repr(C)]
+pub struct QueryCursor {
+    base: sqlite3_vtab_cursor,
+    row_idx: usize,
+    df: DataFrame,
+    arguments: Vec<Option<String>>,
+    /// Data of the last `filter` with the url, format and options it was
+    /// loaded with, so a join calling `filter` once per outer row fetches
+    /// it only once.
+    loaded: Option<([Option<String>; 3], DataFrame)>,
+}
+
+impl QueryCursor {
+    fn new() -> QueryCursor {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        QueryCursor {
+            base,
+            row_idx: 0,
+            df: DataFrame::empty(),
+            arguments: vec![None; ARGUMENTS.len()],
+            loaded: None,
+        }
+    }
+}
+
+impl VTabCursor for QueryCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        args: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let mut args = args.iter();
+        for (i, argument) in self.arguments.iter_mut().enumerate() {
+            *argument = match (idx_num & (1 << i) != 0, args.next()) {
+                (true, Some(arg)) if !matches!(api::value_type(arg), api::ValueType::Null) => {
+                    Some(api::value_text(arg)?.to_string())
+                }
+                _ => None,
+            };
+        }
+        let (Some(url), format, Some(query), options) = (
+            self.arguments[0].as_deref(),
+            self.arguments[1].as_deref(),
+            self.arguments[2].clone(),
+            self.arguments[3].as_deref(),
+        ) else {
+            return Err(Error::new_message("httpfs_query needs a URL and a query"));
+        };
+
+        let key = [
+            Some(url.to_string()),
+            format.map(str::to_string),
+            options.map(str::to_string),
+        ];
+        let df = match &self.loaded {
+            Some((loaded_key, df)) if loaded_key == &key => df.clone(),
+            _ => {
+                let opts = table_opts(url, format, options)?;
+                let df = UrlTable::load(&opts, &HashMap::new())?
+                    .ok_or_else(|| Error::new_message("Internal bug: nothing fetched"))?
+                    .df;
+                self.loaded = Some((key, df.clone()));
+                df
+            }
+        };
+        self.df = run_query(df, &query).map_err(|e| Error::new_message(format!("{}", e)))?;
+        self.row_idx = 0;
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.row_idx += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.row_idx >= self.df.height()
+    }
+
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let i = i as usize;
+        match i.checked_sub(RESULT_COLUMNS) {
+            Some(argument) => match self.arguments.get(argument).cloned().flatten() {
+                Some(value) => api::result_text(ctx, &value)?,
+                None => api::result_null(ctx),
+            },
+            None => match self.df.select_at_idx(i) {
+                Some(col) => result_value(ctx, col.get(self.row_idx))?,
+                None => api::result_null(ctx),
+            },
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.row_idx as i64)
+    }
+}
+
+/// Options of the data, parsed like the arguments of `CREATE VIRTUAL TABLE`.
+/// The data is always read into memory.
+fn table_opts(url: &str, format: Option<&str>, options: Option<&str>) -> Result<TableOpts> {
+    let mut args = options.map(split_options).unwrap_or_default();
+    args.push(format!("url={}", url));
+    if let Some(format) = format.filter(|f| !f.is_empty()) {
+        args.push(format!("format={}", format));
+    }
+    let mut opts = UrlTable::parse_opts(args)?;
+    opts.storage = StorageOpts::MEM;
+    Ok(opts)
+}
+
+/// Runs `query` with `df` registered as the table `t`.
+fn run_query(df: DataFrame, query: &str) -> std::result::Result<DataFrame, Box<dyn StdError>> {
+    let mut ctx = SQLContext::new();
+    ctx.register(TABLE_NAME, df.lazy());
+    let df = ctx
+        .execute(query)
+        .and_then(|lf| lf.collect())
+        .map_err(|e| format!("Polars SQL error: {}", e))?;
+    if df.width() > RESULT_COLUMNS {
+        return Err(format!(
+            "httpfs_query returns at most {} columns, the query has {}",
+            RESULT_COLUMNS,
+            df.width()
+        )
+        .into());
+    }
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fmt::VTabDataFormats;
+
+    fn cities() -> DataFrame {
+        df!(
+            "name" => ["Chicago", "Dallas", "Houston", "Springfield"],
+            "state" => ["IL", "TX", "TX", "IL"],
+            "pop" => [2705627i64, 1219399, 2129784, 114394],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run_query() {
+        let df = run_query(
+            cities(),
+            "SELECT state, sum(pop) AS pop FROM t GROUP BY state ORDER BY state",
+        )
+        .unwrap();
+        let expected = df!(
+            "state" => ["IL", "TX"],
+            "pop" => [2820021i64, 3349183],
+        )
+        .unwrap();
+        assert_eq!(df, expected);
+    }
+
+    #[test]
+    fn test_table_opts() {
+        let opts = table_opts(
+            "https://example.com/data.csv?fields=a,b",
+            Some("csv"),
+            Some("delimiter=';', skip_rows=1, storage=sqlite, url=ignored"),
+        )
+        .unwrap();
+        assert_eq!(opts.url, "https://example.com/data.csv?fields=a,b");
+        assert_eq!(opts.format, Some(VTabDataFormats::CSV));
+        assert_eq!(opts.storage, StorageOpts::MEM);
+        assert_eq!(opts.csv_opts.delimiter, b';');
+        assert_eq!(opts.csv_opts.skip_rows, 1);
+
+        let opts = table_opts("data.json", Some(""), None).unwrap();
+        assert_eq!(opts.format, None);
+        assert!(table_opts("data.csv", Some("csv"), Some("timeout=soon")).is_err());
+    }
+
+    #[test]
+    fn test_run_query_errors() {
+        let err = run_query(cities(), "SELECT * FROM missing").unwrap_err();
+        assert!(err.to_string().starts_with("Polars SQL error"));
+
+        let columns = (0..=RESULT_COLUMNS)
+            .map(|i| format!("pop AS p{}", i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let err = run_query(cities(), &format!("SELECT {} FROM t", columns)).unwrap_err();
+        assert!(err.to_string().contains("at most 16 columns"));
+    }
+}
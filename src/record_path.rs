@@ -0,0 +1,217 @@
+use std::{error::Error, fmt};
+
+use serde_json::Value;
+
+/// One step of a [`RecordPath`].
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// An object member. In a JSON pointer it may also be an array index.
+    Key(String),
+    Index(usize),
+    /// Every element of an array or value of an object.
+    Wildcard,
+}
+
+/// Location of the records in a JSON document, given by the `RECORD_PATH`
+/// option either as a JSON pointer, `/data/items`, or as a JSONPath made of
+/// plain steps, `$.data.items`, `$.pages[*].items` or `$['data']`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordPath {
+    raw: String,
+    steps: Vec<Step>,
+}
+
+impl fmt::Display for RecordPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+pub fn parse_record_path(path: &str) -> Result<RecordPath, Box<dyn Error>> {
+    let raw = path.trim();
+    let steps = if raw.is_empty() {
+        Vec::new()
+    } else if let Some(pointer) = raw.strip_prefix('/') {
+        pointer
+            .split('/')
+            .map(|token| Step::Key(token.replace("~1", "/").replace("~0", "~")))
+            .collect()
+    } else if let Some(rest) = raw.strip_prefix('$') {
+        parse_json_path(rest).ok_or_else(|| format!("Unsupported RECORD_PATH: {}", raw))?
+    } else {
+        return Err(format!(
+            "Invalid RECORD_PATH: {} (expected a JSON pointer or a JSONPath)",
+            raw
+        )
+        .into());
+    };
+    Ok(RecordPath {
+        raw: raw.to_string(),
+        steps,
+    })
+}
+
+/// Parses the steps after `$`. Recursive descent, filters, slices and unions
+/// are not supported.
+fn parse_json_path(mut rest: &str) -> Option<Vec<Step>> {
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            if after.starts_with('.') {
+                return None;
+            }
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let step = match &after[..end] {
+                "" => return None,
+                "*" => Step::Wildcard,
+                key => Step::Key(key.to_string()),
+            };
+            steps.push(step);
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let (step, len) = match after.chars().next()? {
+                quote @ ('\'' | '"') => {
+                    let end = after[1..].find(quote)? + 1;
+                    (Step::Key(after[1..end].to_string()), end + 1)
+                }
+                _ => {
+                    let end = after.find(']')?;
+                    let step = match after[..end].trim() {
+                        "*" => Step::Wildcard,
+                        index => Step::Index(index.parse().ok()?),
+                    };
+                    (step, end)
+                }
+            };
+            rest = after[len..].strip_prefix(']')?;
+            steps.push(step);
+        } else {
+            return None;
+        }
+    }
+    Some(steps)
+}
+
+impl RecordPath {
+    /// Values the path leads to in `value`. Wildcards may match several.
+    fn find<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut matches = vec![value];
+        for step in &self.steps {
+            matches = matches
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (step, value) {
+                        (Step::Key(key), Value::Object(map)) => map.get(key).into_iter().collect(),
+                        (Step::Key(key), Value::Array(items)) => key
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|i| items.get(i))
+                            .into_iter()
+                            .collect(),
+                        (Step::Index(i), Value::Array(items)) => {
+                            items.get(*i).into_iter().collect()
+                        }
+                        (Step::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Step::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        matches
+    }
+
+    /// Collects the records at the path in a JSON document, or in every line
+    /// of a JSON Lines file, and returns them as a JSON array. Arrays found
+    /// at the path hold records, any other value is a record itself.
+    pub fn select(&self, data: &[u8], lines: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+        let documents = if lines {
+            data.split(|b| *b == b'\n')
+                .filter(|line| !line.trim_ascii().is_empty())
+                .map(serde_json::from_slice)
+                .collect::<Result<Vec<Value>, _>>()?
+        } else {
+            vec![serde_json::from_slice(data)?]
+        };
+
+        let mut records = Vec::new();
+        for document in &documents {
+            for value in self.find(document) {
+                match value {
+                    Value::Array(items) => records.extend(items),
+                    other => records.push(other),
+                }
+            }
+        }
+        if records.is_empty() {
+            return Err(format!("RECORD_PATH {} matched no records", self).into());
+        }
+        Ok(serde_json::to_vec(&records)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(path: &str, data: &str, lines: bool) -> Value {
+        let selected = parse_record_path(path)
+            .unwrap()
+            .select(data.as_bytes(), lines)
+            .unwrap();
+        serde_json::from_slice(&selected).unwrap()
+    }
+
+    #[test]
+    fn test_parse_record_path() {
+        let steps = |path| parse_record_path(path).unwrap().steps;
+        let key = |k: &str| Step::Key(k.to_string());
+        assert_eq!(steps("/data/items"), [key("data"), key("items")]);
+        assert_eq!(steps("/a~1b/m~0n/0"), [key("a/b"), key("m~n"), key("0")]);
+        assert_eq!(steps("$.data.items"), [key("data"), key("items")]);
+        assert_eq!(
+            steps("$.pages[*].items[0]"),
+            [key("pages"), Step::Wildcard, key("items"), Step::Index(0)]
+        );
+        assert_eq!(steps("$['odd.key'][\"x\"]"), [key("odd.key"), key("x")]);
+        assert!(steps("$").is_empty());
+        assert!(steps("").is_empty());
+
+        assert!(parse_record_path("data.items").is_err());
+        assert!(parse_record_path("$..items").is_err());
+        assert!(parse_record_path("$.items[?(@.id)]").is_err());
+        assert!(parse_record_path("$.items[0").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let data = r#"{"data": {"items": [{"id": 1}, {"id": 2}]}, "next": null}"#;
+        let expected = json!([{"id": 1}, {"id": 2}]);
+        assert_eq!(select("/data/items", data, false), expected);
+        assert_eq!(select("$.data.items", data, false), expected);
+        assert_eq!(select("$.data.items[1]", data, false), json!([{"id": 2}]));
+
+        let pages = r#"{"pages": [{"items": [{"id": 1}]}, {"items": [{"id": 2}]}]}"#;
+        assert_eq!(select("$.pages[*].items", pages, false), expected);
+        assert_eq!(select("/pages/1/items", pages, false), json!([{"id": 2}]));
+    }
+
+    #[test]
+    fn test_select_lines() {
+        let data =
+            "{\"page\": 1, \"items\": [{\"id\": 1}]}\n\n{\"page\": 2, \"items\": [{\"id\": 2}]}\n";
+        assert_eq!(select("$.items", data, true), json!([{"id": 1}, {"id": 2}]));
+    }
+
+    #[test]
+    fn test_select_errors() {
+        let path = parse_record_path("/data/missing").unwrap();
+        let err = path.select(br#"{"data": {}}"#, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "RECORD_PATH /data/missing matched no records"
+        );
+        assert!(path.select(b"not json", false).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock, Weak},
+};
+
+use polars::prelude::DataFrame;
+use sqlite_loadable::{
+    api,
+    prelude::{sqlite3, sqlite3_context, sqlite3_value},
+    Error, Result,
+};
+
+use crate::{stats::TableStats, storage::Statement, storage::StorageOpts, TableOpts, UrlTable};
+
+/// A table connected in some database, as seen by `httpfs_refresh`.
+#[derive(Clone)]
+struct LiveTable {
+    db: usize,
+    module_name: String,
+    table_name: String,
+    opts: Arc<TableOpts>,
+    df: Weak<RwLock<DataFrame>>,
+    stats: Weak<Mutex<Option<TableStats>>>,
+}
+
+static LIVE_TABLES: Mutex<Vec<LiveTable>> = Mutex::new(Vec::new());
+
+/// Records a connected table so `httpfs_refresh` can find it. Entries of
+/// disconnected tables are dropped along the way.
+pub fn register(
+    db: *mut sqlite3,
+    module_name: &str,
+    table_name: &str,
+    opts: Arc<TableOpts>,
+    df: &Arc<RwLock<DataFrame>>,
+    stats: &Arc<Mutex<Option<TableStats>>>,
+) {
+    let mut tables = LIVE_TABLES.lock().unwrap_or_else(|e| e.into_inner());
+    tables.retain(|t| {
+        t.df.strong_count() > 0 && !(t.db == db as usize && t.table_name == table_name)
+    });
+    tables.push(LiveTable {
+        db: db as usize,
+        module_name: module_name.to_string(),
+        table_name: table_name.to_string(),
+        opts,
+        df: Arc::downgrade(df),
+        stats: Arc::downgrade(stats),
+    });
+}
+
+fn find(db: *mut sqlite3, table_name: &str) -> Option<(LiveTable, Arc<RwLock<DataFrame>>)> {
+    let tables = LIVE_TABLES.lock().unwrap_or_else(|e| e.into_inner());
+    tables
+        .iter()
+        .filter(|t| t.db == db as usize && t.table_name.eq_ignore_ascii_case(table_name))
+        .find_map(|t| Some((t.clone(), t.df.upgrade()?)))
+}
+
+/// `httpfs_refresh('table')`: downloads the sources of a table again when
+/// they changed, replacing its data in memory and in the shadow tables.
+/// Returns 1 when the data was replaced, 0 when every source was unchanged.
+pub fn httpfs_refresh(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let db = api::context_db_handle(context);
+    let name = api::value_text(&values[0])?;
+
+    let live = match find(db, name) {
+        Some(live) => live,
+        None => {
+            // Preparing a statement on the table connects it.
+            let sql = format!("SELECT 1 FROM \"{}\" LIMIT 0;", name.replace('"', "\"\""));
+            Statement::build(db, &sql)
+                .map_err(|_| Error::new_message(format!("No such httpfs table: {}", name)))?
+                .finalize()
+                .map_err(|e| Error::new_message(e.to_string()))?;
+            find(db, name)
+                .ok_or_else(|| Error::new_message(format!("No such httpfs table: {}", name)))?
+        }
+    };
+    let (
+        LiveTable {
+            module_name,
+            table_name,
+            opts,
+            stats,
+            ..
+        },
+        df,
+    ) = live;
+
+    if opts.storage == StorageOpts::REMOTE {
+        return Err(Error::new_message(
+            "REMOTE tables read their source on every query",
+        ));
+    }
+
+    let validators = match opts.storage {
+        StorageOpts::SQLITE => UrlTable::read_validators(db, &module_name, &table_name),
+        _ => HashMap::new(),
+    };
+    let loaded = match UrlTable::load(&opts, &validators)? {
+        Some(loaded) => loaded,
+        None => {
+            api::result_int(context, 0);
+            return Ok(());
+        }
+    };
+
+    let columns = df
+        .read()
+        .map_err(|_| Error::new_message("Table lock poisoned"))?
+        .get_column_names_owned();
+    if loaded.df.get_column_names_owned() != columns {
+        return Err(Error::new_message(format!(
+            "The columns of {} changed, recreate the table",
+            table_name
+        )));
+    }
+
+    if opts.storage == StorageOpts::SQLITE {
+        UrlTable::write_shadow_tables(db, &module_name, &table_name, &opts, &loaded)?;
+    }
+    *df.write()
+        .map_err(|_| Error::new_message("Table lock poisoned"))? = loaded.df;
+    if let Some(stats) = stats.upgrade() {
+        *stats.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    api::result_int(context, 1);
+    Ok(())
+}
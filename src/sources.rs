@@ -0,0 +1,249 @@
+use std::error::Error;
+
+use reqwest::Url;
+
+use crate::fetch::{fetch, get_source, redact_url, DataSource, FetchOpts};
+
+/// Expands the `URL` argument into the sources a table is built from.
+///
+/// The argument is a comma separated list where every entry is one of:
+/// - a URL or path, as accepted by [`get_source`];
+/// - a glob over local paths (`data/events-*.csv`), expanded in sorted order;
+/// - `@` followed by the URL or path of a manifest, a text file listing one
+///   source per line. Relative entries are resolved against the manifest
+///   location, blank lines and lines starting with `#` are skipped.
+pub fn expand_sources(spec: &str, opts: &FetchOpts) -> Result<Vec<DataSource>, Box<dyn Error>> {
+    let mut sources = Vec::new();
+    for entry in split_sources(spec) {
+        match entry.strip_prefix('@') {
+            Some(manifest) => {
+                for line in read_manifest(manifest, opts)? {
+                    sources.extend(expand_entry(&line)?);
+                }
+            }
+            None => sources.extend(expand_entry(entry)?),
+        }
+    }
+
+    if sources.is_empty() {
+        return Err("Empty URL".into());
+    }
+    Ok(sources)
+}
+
+/// The entries of a comma separated `URL` argument, as written by the user.
+/// Commas in the query string or fragment of a URL, as in `?fields=a,b`,
+/// belong to it unless another URL or a manifest follows them.
+pub fn split_sources(spec: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    for (comma, _) in spec.match_indices(',') {
+        if in_query(&spec[start..comma]) && !starts_entry(&spec[comma + 1..]) {
+            continue;
+        }
+        entries.push(spec[start..comma].trim());
+        start = comma + 1;
+    }
+    entries.push(spec[start..].trim());
+    entries.retain(|entry| !entry.is_empty());
+    entries
+}
+
+/// Whether `entry` is an HTTP URL that already reached its query string or
+/// fragment.
+fn in_query(entry: &str) -> bool {
+    let entry = entry.trim_start().to_lowercase();
+    (entry.starts_with("http://") || entry.starts_with("https://")) && entry.contains(['?', '#'])
+}
+
+/// Whether `rest` starts with a URL of any scheme or a manifest.
+fn starts_entry(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.starts_with('@')
+        || rest.split_once("://").is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+}
+
+/// [`redact_url`] applied to every entry of a `URL` argument.
+pub fn redact_sources(spec: &str) -> String {
+    split_sources(spec)
+        .into_iter()
+        .map(|entry| match entry.strip_prefix('@') {
+            Some(manifest) => format!("@{}", redact_url(manifest)),
+            None => redact_url(entry),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+fn expand_entry(entry: &str) -> Result<Vec<DataSource>, Box<dyn Error>> {
+    match get_source(entry)? {
+        DataSource::FILE(path) if is_glob(&path.to_string_lossy()) => {
+            let pattern = path.to_string_lossy();
+            let mut paths = glob::glob(&pattern)
+                .map_err(|e| format!("Invalid glob pattern: {}: {}", pattern, e))?
+                .collect::<Result<Vec<_>, _>>()?;
+            if paths.is_empty() {
+                return Err(format!("No files match: {}", pattern).into());
+            }
+            paths.sort();
+            Ok(paths.into_iter().map(DataSource::FILE).collect())
+        }
+        source => Ok(vec![source]),
+    }
+}
+
+fn read_manifest(manifest: &str, opts: &FetchOpts) -> Result<Vec<String>, Box<dyn Error>> {
+    let source = get_source(manifest)?;
+    let body = fetch(&source, opts)?.body;
+    let text =
+        String::from_utf8(body).map_err(|_| format!("Manifest is not valid UTF-8: {}", source))?;
+
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| resolve_entry(&source, line))
+        .collect()
+}
+
+fn resolve_entry(manifest: &DataSource, entry: &str) -> Result<String, Box<dyn Error>> {
+    if entry.contains("://") {
+        return Ok(entry.to_string());
+    }
+    match manifest {
+        DataSource::HTTP(url) => Ok(Url::parse(url)?.join(entry)?.to_string()),
+        DataSource::FILE(path) => Ok(path
+            .parent()
+            .map(|dir| dir.join(entry))
+            .unwrap_or_else(|| entry.into())
+            .to_string_lossy()
+            .to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_split_sources() {
+        assert_eq!(
+            split_sources(" a.csv, https://example.com/b.csv ,,"),
+            vec!["a.csv", "https://example.com/b.csv"]
+        );
+    }
+
+    #[test]
+    fn test_split_sources_commas_in_query() {
+        assert_eq!(
+            split_sources("https://example.com/api?fields=a,b&sig=x,y"),
+            vec!["https://example.com/api?fields=a,b&sig=x,y"]
+        );
+        assert_eq!(
+            split_sources("https://example.com/a?fields=a,b, https://example.com/b.csv, @list.txt"),
+            vec![
+                "https://example.com/a?fields=a,b",
+                "https://example.com/b.csv",
+                "@list.txt"
+            ]
+        );
+        assert_eq!(
+            split_sources("https://example.com/a.csv,b.csv"),
+            vec!["https://example.com/a.csv", "b.csv"]
+        );
+    }
+
+    #[test]
+    fn test_redact_sources() {
+        assert_eq!(
+            redact_sources("https://u:p@example.com/a.csv, @https://u:p@example.com/list.txt"),
+            "https://example.com/a.csv, @https://example.com/list.txt"
+        );
+    }
+
+    #[test]
+    fn test_expand_sources_list() {
+        let sources =
+            expand_sources("https://example.com/a.csv, b.csv", &FetchOpts::default()).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                DataSource::HTTP("https://example.com/a.csv".to_string()),
+                DataSource::FILE("b.csv".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_sources_glob() {
+        let dir = temp_dir("sqlite_httpfs_test_glob");
+        for name in ["events-02.csv", "events-01.csv", "other.csv"] {
+            fs::write(dir.join(name), "a\n1\n").unwrap();
+        }
+
+        let pattern = dir.join("events-*.csv");
+        let sources = expand_sources(&pattern.to_string_lossy(), &FetchOpts::default()).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                DataSource::FILE(dir.join("events-01.csv")),
+                DataSource::FILE(dir.join("events-02.csv")),
+            ]
+        );
+
+        let pattern = dir.join("missing-*.csv");
+        let err = expand_sources(&pattern.to_string_lossy(), &FetchOpts::default()).unwrap_err();
+        assert!(err.to_string().starts_with("No files match"));
+    }
+
+    #[test]
+    fn test_expand_sources_manifest() {
+        let dir = temp_dir("sqlite_httpfs_test_manifest");
+        fs::write(dir.join("a.csv"), "a\n1\n").unwrap();
+        fs::write(
+            dir.join("list.txt"),
+            "# daily files\na.csv\n\nhttps://example.com/b.csv\n",
+        )
+        .unwrap();
+
+        let spec = format!("@{}", dir.join("list.txt").display());
+        let sources = expand_sources(&spec, &FetchOpts::default()).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                DataSource::FILE(dir.join("a.csv")),
+                DataSource::HTTP("https://example.com/b.csv".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_entry_http() {
+        let manifest = DataSource::HTTP("https://example.com/data/list.txt".to_string());
+        assert_eq!(
+            resolve_entry(&manifest, "2026/a.csv").unwrap(),
+            "https://example.com/data/2026/a.csv"
+        );
+    }
+
+    #[test]
+    fn test_expand_sources_empty() {
+        assert!(expand_sources(" , ", &FetchOpts::default()).is_err());
+    }
+}
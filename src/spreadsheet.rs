@@ -0,0 +1,632 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    io::{Cursor, Read},
+};
+
+use calamine::{open_workbook_from_rs, Data, Ods, Range, Reader, Sheets, Xls, Xlsx};
+use polars::prelude::{Column, DataFrame, DataType, NamedFrom, PolarsResult, Series, TimeUnit};
+use zip::ZipArchive;
+
+use crate::fmt::VTabDataFormats;
+
+/// Signature of OLE compound files, the container of legacy `.xls` files.
+const OLE_MAGIC: &[u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+const ODS_MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+/// Cells selected by the `RANGE` option, as 0-based rows and columns. A
+/// missing end extends to the last used row or column of the sheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellRange {
+    pub start: (u32, u32),
+    pub end_row: Option<u32>,
+    pub end_col: Option<u32>,
+}
+
+/// Parses a range in A1 notation: `B2:D20`, `A:C` (whole columns), or a
+/// single cell like `B3` for everything below and to the right of it.
+pub fn parse_range(range: &str) -> Result<CellRange, Box<dyn Error>> {
+    let invalid = || format!("Invalid RANGE: {}", range);
+    let (start, end) = match range.split_once(':') {
+        Some((start, end)) => (start, Some(end)),
+        None => (range, None),
+    };
+    let (start_row, start_col) = parse_cell(start).ok_or_else(invalid)?;
+    let (end_row, end_col) = match end {
+        Some(end) => {
+            let (row, col) = parse_cell(end).ok_or_else(invalid)?;
+            (row, Some(col))
+        }
+        None => (None, None),
+    };
+
+    let start = (start_row.unwrap_or(0), start_col);
+    if end_row.is_some_and(|row| row < start.0) || end_col.is_some_and(|col| col < start.1) {
+        return Err(invalid().into());
+    }
+    Ok(CellRange {
+        start,
+        end_row,
+        end_col,
+    })
+}
+
+/// A1 name of a 0-based cell position, the inverse of [`parse_cell`].
+fn cell_name((row, col): (u32, u32)) -> String {
+    let mut letters = Vec::new();
+    let mut col = col + 1;
+    while col > 0 {
+        letters.push((b'A' + ((col - 1) % 26) as u8) as char);
+        col = (col - 1) / 26;
+    }
+    letters.iter().rev().collect::<String>() + &(row + 1).to_string()
+}
+
+/// Parses `B12` into its 0-based row and column. The row may be left out.
+fn parse_cell(cell: &str) -> Option<(Option<u32>, u32)> {
+    let cell = cell.trim();
+    let digits = cell
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(cell.len());
+    let (letters, digits) = cell.split_at(digits);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let col = letters.chars().try_fold(0u32, |col, c| {
+        let value = c.to_ascii_uppercase() as u32 - 'A' as u32 + 1;
+        col.checked_mul(26)?.checked_add(value)
+    })?;
+    let row = match digits {
+        "" => None,
+        digits => Some(digits.parse::<u32>().ok()?.checked_sub(1)?),
+    };
+    Some((row, col - 1))
+}
+
+/// Recognises spreadsheets by their content, so that `.xlsx` and `.ods`
+/// files are not mistaken for zip archives.
+pub fn detect_spreadsheet(bytes: &[u8]) -> Option<VTabDataFormats> {
+    if bytes.starts_with(OLE_MAGIC) {
+        return Some(VTabDataFormats::XLS);
+    }
+    if !bytes.starts_with(b"PK\x03\x04") {
+        return None;
+    }
+
+    let mut zip = ZipArchive::new(Cursor::new(bytes)).ok()?;
+    if zip.index_for_name("xl/workbook.xml").is_some() {
+        return Some(VTabDataFormats::XLSX);
+    }
+    // OpenDocument files store their media type in the `mimetype` entry.
+    let mut mimetype = String::new();
+    zip.by_name("mimetype")
+        .ok()?
+        .read_to_string(&mut mimetype)
+        .ok()?;
+    (mimetype.trim() == ODS_MIMETYPE).then_some(VTabDataFormats::ODS)
+}
+
+/// Reads one sheet of an XLSX, XLS or ODS workbook. A first row made only
+/// of text is taken as the header, column types are inferred from the
+/// cells below it.
+pub struct SpreadsheetReader<'a> {
+    data: &'a [u8],
+    format: VTabDataFormats,
+    sheet: Option<&'a str>,
+    range: Option<CellRange>,
+}
+
+impl<'a> SpreadsheetReader<'a> {
+    pub fn new(data: &'a [u8], format: VTabDataFormats) -> Self {
+        Self {
+            data,
+            format,
+            sheet: None,
+            range: None,
+        }
+    }
+
+    /// Sheet to read, by name or 1-based position. Defaults to the first.
+    pub fn with_sheet(mut self, sheet: Option<&'a str>) -> Self {
+        self.sheet = sheet;
+        self
+    }
+
+    pub fn with_range(mut self, range: Option<CellRange>) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn finish(self) -> Result<DataFrame, Box<dyn Error>> {
+        let data = Cursor::new(self.data);
+        let mut workbook = match self.format {
+            VTabDataFormats::XLSX => Sheets::Xlsx(open_workbook_from_rs::<Xlsx<_>, _>(data)?),
+            VTabDataFormats::XLS => Sheets::Xls(open_workbook_from_rs::<Xls<_>, _>(data)?),
+            VTabDataFormats::ODS => Sheets::Ods(open_workbook_from_rs::<Ods<_>, _>(data)?),
+            format => return Err(format!("{} is not a spreadsheet format", format.as_str()).into()),
+        };
+
+        let names = workbook.sheet_names();
+        let name = match self.sheet {
+            None => names.first(),
+            Some(sheet) => names
+                .iter()
+                .find(|name| name.as_str() == sheet)
+                .or_else(|| names.iter().find(|name| name.eq_ignore_ascii_case(sheet)))
+                .or_else(|| {
+                    let position = sheet.trim().parse::<usize>().ok()?;
+                    names.get(position.checked_sub(1)?)
+                }),
+        }
+        .ok_or_else(|| match self.sheet {
+            Some(sheet) => format!("Sheet not found: {} (sheets: {})", sheet, names.join(", ")),
+            None => "The workbook has no sheets".to_string(),
+        })?
+        .clone();
+
+        let sheet = workbook.worksheet_range(&name)?;
+        let cells = match (self.range, sheet.end()) {
+            // Ends stop at the used area, calamine panics when it ends before
+            // the range starts.
+            (Some(range), Some(end)) if range.start.0 > end.0 || range.start.1 > end.1 => {
+                return Err(format!(
+                    "RANGE starts at {}, outside sheet {} which ends at {}",
+                    cell_name(range.start),
+                    name,
+                    cell_name(end)
+                )
+                .into())
+            }
+            (Some(range), Some((last_row, last_col))) => sheet.range(
+                range.start,
+                (
+                    range.end_row.map_or(last_row, |row| row.min(last_row)),
+                    range.end_col.map_or(last_col, |col| col.min(last_col)),
+                ),
+            ),
+            (Some(_), None) => Range::empty(),
+            (None, _) => sheet,
+        };
+        Self::to_dataframe(&cells)
+    }
+
+    fn to_dataframe(cells: &Range<Data>) -> Result<DataFrame, Box<dyn Error>> {
+        let rows = cells
+            .rows()
+            .filter(|row| !row.iter().all(|cell| matches!(cell, Data::Empty)))
+            .collect::<Vec<_>>();
+        let width = cells.width();
+
+        let header = rows.first().filter(|row| {
+            row.iter()
+                .all(|cell| matches!(cell, Data::String(_) | Data::Empty))
+                && row.iter().any(|cell| matches!(cell, Data::String(_)))
+        });
+        let rows = match header {
+            Some(_) => &rows[1..],
+            None => &rows[..],
+        };
+
+        let mut names = HashSet::new();
+        let columns = (0..width)
+            .map(|i| {
+                let name = match header.map(|row| &row[i]) {
+                    Some(Data::String(name)) if !name.trim().is_empty() => name.trim().to_string(),
+                    _ => format!("column_{}", i + 1),
+                };
+                // Polars needs unique names, repeated headers get a suffix.
+                let mut unique = name.clone();
+                let mut n = 1;
+                while !names.insert(unique.clone()) {
+                    unique = format!("{}_{}", name, n);
+                    n += 1;
+                }
+                Self::column(&unique, rows.iter().map(|row| &row[i]))
+            })
+            .collect::<PolarsResult<Vec<_>>>()?;
+
+        Ok(DataFrame::new(columns)?)
+    }
+
+    /// Builds a column of the narrowest type holding all of `cells`:
+    /// integers, floats, booleans or datetimes, and text otherwise.
+    fn column<'c>(
+        name: &str,
+        cells: impl Iterator<Item = &'c Data> + Clone,
+    ) -> PolarsResult<Column> {
+        let kind = cells
+            .clone()
+            .filter_map(CellKind::of)
+            .reduce(CellKind::merge)
+            .unwrap_or(CellKind::Text);
+
+        let series = match kind {
+            CellKind::Int => Series::new(
+                name.into(),
+                cells
+                    .map(|cell| match cell {
+                        Data::Int(i) => Some(*i),
+                        Data::Float(f) => Some(*f as i64),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            CellKind::Float => Series::new(
+                name.into(),
+                cells
+                    .map(|cell| match cell {
+                        Data::Int(i) => Some(*i as f64),
+                        Data::Float(f) => Some(*f),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            CellKind::Bool => Series::new(
+                name.into(),
+                cells
+                    .map(|cell| match cell {
+                        Data::Bool(b) => Some(*b),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            CellKind::DateTime => Series::new(
+                name.into(),
+                cells
+                    .map(|cell| match cell {
+                        Data::DateTime(dt) => {
+                            dt.as_datetime().map(|dt| dt.and_utc().timestamp_millis())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?,
+            CellKind::Text => Series::new(name.into(), cells.map(text).collect::<Vec<_>>()),
+        };
+        Ok(Column::new(series.name().clone(), series))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CellKind {
+    Int,
+    Float,
+    Bool,
+    DateTime,
+    Text,
+}
+
+impl CellKind {
+    /// Kind of a cell, `None` for empty and error cells, which become nulls.
+    fn of(cell: &Data) -> Option<CellKind> {
+        match cell {
+            Data::Empty | Data::Error(_) => None,
+            Data::Int(_) => Some(CellKind::Int),
+            // Spreadsheets store every number as a float.
+            Data::Float(f) if f.fract() == 0.0 && f.abs() < (1u64 << 53) as f64 => {
+                Some(CellKind::Int)
+            }
+            Data::Float(_) => Some(CellKind::Float),
+            Data::Bool(_) => Some(CellKind::Bool),
+            Data::DateTime(dt) if !dt.is_duration() => Some(CellKind::DateTime),
+            _ => Some(CellKind::Text),
+        }
+    }
+
+    fn merge(self, other: CellKind) -> CellKind {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (CellKind::Int, CellKind::Float) | (CellKind::Float, CellKind::Int) => CellKind::Float,
+            _ => CellKind::Text,
+        }
+    }
+}
+
+/// Text of a cell in a column of mixed types.
+fn text(cell: &Data) -> Option<String> {
+    match cell {
+        Data::Empty | Data::Error(_) => None,
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => Some(s.clone()),
+        Data::Int(i) => Some(i.to_string()),
+        Data::Float(f) => Some(f.to_string()),
+        Data::Bool(b) => Some(b.to_string()),
+        Data::DateTime(dt) => Some(match dt.as_datetime() {
+            Some(datetime) if !dt.is_duration() => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+            _ => dt.as_f64().to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn zip(entries: &[(&str, String)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Cells are numbers, `d:<serial>` dates, `b:<0|1>` booleans or text.
+    fn worksheet(rows: &[(u32, &[(&str, &str)])]) -> String {
+        let rows = rows
+            .iter()
+            .map(|(r, cells)| {
+                let cells = cells
+                    .iter()
+                    .map(|(col, value)| {
+                        let pos = format!("{}{}", col, r);
+                        if let Some(serial) = value.strip_prefix("d:") {
+                            format!("<c r=\"{}\" s=\"1\"><v>{}</v></c>", pos, serial)
+                        } else if let Some(b) = value.strip_prefix("b:") {
+                            format!("<c r=\"{}\" t=\"b\"><v>{}</v></c>", pos, b)
+                        } else if value.parse::<f64>().is_ok() {
+                            format!("<c r=\"{}\"><v>{}</v></c>", pos, value)
+                        } else {
+                            format!(
+                                "<c r=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+                                pos, value
+                            )
+                        }
+                    })
+                    .collect::<String>();
+                format!("<row r=\"{}\">{}</row>", r, cells)
+            })
+            .collect::<String>();
+        format!(
+            "<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><sheetData>{}</sheetData></worksheet>",
+            rows
+        )
+    }
+
+    fn xlsx(sheets: &[(&str, String)]) -> Vec<u8> {
+        let names = sheets
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                format!(
+                    "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+                    name,
+                    i + 1,
+                    i + 1
+                )
+            })
+            .collect::<String>();
+        let rels = (1..=sheets.len())
+            .map(|i| {
+                format!(
+                    "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+                    i, i
+                )
+            })
+            .collect::<String>();
+        let mut entries = vec![
+            (
+                "xl/workbook.xml",
+                format!(
+                    "<workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><sheets>{}</sheets></workbook>",
+                    names
+                ),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                format!(
+                    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">{}</Relationships>",
+                    rels
+                ),
+            ),
+            (
+                "xl/styles.xml",
+                "<styleSheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><cellXfs count=\"2\"><xf numFmtId=\"0\"/><xf numFmtId=\"14\" applyNumberFormat=\"1\"/></cellXfs></styleSheet>".to_string(),
+            ),
+        ];
+        let paths = (1..=sheets.len())
+            .map(|i| format!("xl/worksheets/sheet{}.xml", i))
+            .collect::<Vec<_>>();
+        for (path, (_, sheet)) in paths.iter().zip(sheets) {
+            entries.push((path.as_str(), sheet.clone()));
+        }
+        zip(&entries)
+    }
+
+    fn workbook() -> Vec<u8> {
+        let summary = worksheet(&[
+            (
+                1,
+                &[
+                    ("A", "state"),
+                    ("B", "pop"),
+                    ("C", "updated"),
+                    ("D", "active"),
+                ],
+            ),
+            (
+                2,
+                &[
+                    ("A", "IL"),
+                    ("B", "2705627"),
+                    ("C", "d:45658"),
+                    ("D", "b:1"),
+                ],
+            ),
+            (3, &[("A", "TX"), ("B", "3349183.5"), ("D", "b:0")]),
+        ]);
+        let cities = worksheet(&[
+            (1, &[("A", "US cities")]),
+            (3, &[("B", "name"), ("C", "state"), ("D", "pop")]),
+            (4, &[("B", "Chicago"), ("C", "IL"), ("D", "2705627")]),
+            (5, &[("B", "Dallas"), ("C", "TX"), ("D", "1219399")]),
+            (6, &[("B", "Houston"), ("C", "TX"), ("D", "2129784")]),
+        ]);
+        xlsx(&[("Summary", summary), ("Cities", cities)])
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("B2:D20").unwrap(),
+            CellRange {
+                start: (1, 1),
+                end_row: Some(19),
+                end_col: Some(3)
+            }
+        );
+        assert_eq!(
+            parse_range("a:c").unwrap(),
+            CellRange {
+                start: (0, 0),
+                end_row: None,
+                end_col: Some(2)
+            }
+        );
+        assert_eq!(
+            parse_range("AA10").unwrap(),
+            CellRange {
+                start: (9, 26),
+                end_row: None,
+                end_col: None
+            }
+        );
+        assert_eq!(cell_name((0, 0)), "A1");
+        assert_eq!(cell_name((9, 26)), "AA10");
+        assert_eq!(cell_name((1, 701)), "ZZ2");
+        for invalid in ["", "2:3", "D1:B5", "A3:C1", "A0", "B2:"] {
+            assert_eq!(
+                parse_range(invalid).unwrap_err().to_string(),
+                format!("Invalid RANGE: {}", invalid)
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_spreadsheet() {
+        assert_eq!(detect_spreadsheet(&workbook()), Some(VTabDataFormats::XLSX));
+        let ods = zip(&[
+            ("mimetype", ODS_MIMETYPE.to_string()),
+            ("content.xml", String::new()),
+        ]);
+        assert_eq!(detect_spreadsheet(&ods), Some(VTabDataFormats::ODS));
+        assert_eq!(
+            detect_spreadsheet(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\x00"),
+            Some(VTabDataFormats::XLS)
+        );
+        let archive = zip(&[("cities.csv", "name\nChicago\n".to_string())]);
+        assert_eq!(detect_spreadsheet(&archive), None);
+        assert_eq!(detect_spreadsheet(b"name,pop\n"), None);
+    }
+
+    #[test]
+    fn test_read_first_sheet() {
+        let data = workbook();
+        let df = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+            .finish()
+            .unwrap();
+
+        assert_eq!(df.get_column_names(), ["state", "pop", "updated", "active"]);
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::String,
+                DataType::Float64,
+                DataType::Datetime(TimeUnit::Milliseconds, None),
+                DataType::Boolean,
+            ]
+        );
+        let updated = df.column("updated").unwrap();
+        assert_eq!(updated.get(0).unwrap().to_string(), "2025-01-01 00:00:00");
+        assert!(updated.get(1).unwrap().is_null());
+        assert_eq!(
+            df.column("active").unwrap().bool().unwrap().get(1),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_sheet_and_range() {
+        let data = workbook();
+        for sheet in ["Cities", "cities", "2"] {
+            let df = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+                .with_sheet(Some(sheet))
+                .with_range(Some(parse_range("B3:D").unwrap()))
+                .finish()
+                .unwrap();
+            assert_eq!(df.get_column_names(), ["name", "state", "pop"]);
+            assert_eq!(df.height(), 3);
+            assert_eq!(df.column("pop").unwrap().dtype(), &DataType::Int64);
+        }
+
+        // The title row is taken as the header, the table below it as text.
+        let df = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+            .with_sheet(Some("Cities"))
+            .finish()
+            .unwrap();
+        assert_eq!(
+            df.get_column_names(),
+            ["US cities", "column_2", "column_3", "column_4"]
+        );
+
+        let err = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+            .with_sheet(Some("Budget"))
+            .finish()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sheet not found: Budget (sheets: Summary, Cities)"
+        );
+    }
+
+    #[test]
+    fn test_range_outside_sheet() {
+        let data = xlsx(&[(
+            "Sheet1",
+            worksheet(&[
+                (1, &[("A", "name"), ("B", "pop")]),
+                (2, &[("A", "Chicago"), ("B", "2705627")]),
+            ]),
+        )]);
+        for (range, start) in [("A10", "A10"), ("D1", "D1"), ("C1:E5", "C1")] {
+            let err = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+                .with_range(Some(parse_range(range).unwrap()))
+                .finish()
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "RANGE starts at {}, outside sheet Sheet1 which ends at B2",
+                    start
+                )
+            );
+        }
+
+        // Ends past the used area are cut to it.
+        let df = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+            .with_range(Some(parse_range("A1:Z99").unwrap()))
+            .finish()
+            .unwrap();
+        assert_eq!(df.get_column_names(), ["name", "pop"]);
+        assert_eq!(df.height(), 1);
+    }
+
+    #[test]
+    fn test_no_header() {
+        let data = xlsx(&[(
+            "Sheet1",
+            worksheet(&[
+                (1, &[("A", "1"), ("B", "x")]),
+                (2, &[("A", "2"), ("B", "y")]),
+            ]),
+        )]);
+        let df = SpreadsheetReader::new(&data, VTabDataFormats::XLSX)
+            .finish()
+            .unwrap();
+        assert_eq!(df.get_column_names(), ["column_1", "column_2"]);
+        assert_eq!(df.height(), 2);
+    }
+}
@@ -0,0 +1,184 @@
+use polars::prelude::{Column, DataFrame};
+
+use crate::plan::PlanOp;
+
+/// Figures about a column used to estimate how selective a constraint is.
+/// Zero means unknown.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnStats {
+    pub distinct: usize,
+    pub nulls: usize,
+    /// Bounds of a numeric column, `None` when unknown.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Row count and column statistics behind the estimates of `best_index`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableStats {
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+impl TableStats {
+    pub fn from_dataframe(df: &DataFrame) -> Self {
+        let columns = df
+            .get_columns()
+            .iter()
+            .map(|column| {
+                let (min, max) = numeric_bounds(column);
+                ColumnStats {
+                    distinct: column.n_unique().unwrap_or(0),
+                    nulls: column.null_count(),
+                    min,
+                    max,
+                }
+            })
+            .collect();
+        TableStats {
+            rows: df.height(),
+            columns,
+        }
+    }
+
+    /// Fraction of the rows expected to pass `op` on `column`. A range on a
+    /// numeric column is estimated from where `value` falls between the
+    /// column bounds. When SQLite does not know the value while planning, or
+    /// the bounds are unknown, ranges and patterns fall back to fixed guesses
+    /// in the spirit of SQLite's own.
+    pub fn selectivity(&self, column: usize, op: PlanOp, value: Option<f64>) -> f64 {
+        let stats = self.columns.get(column).cloned().unwrap_or_default();
+        let distinct = match stats.distinct {
+            0 => 10.0,
+            distinct => distinct as f64,
+        };
+        let null_fraction = match self.rows {
+            0 => 0.0,
+            rows => stats.nulls as f64 / rows as f64,
+        };
+        match op {
+            PlanOp::EQ => 1.0 / distinct,
+            // The length of an IN list is not known either, assume a few values.
+            PlanOp::IN => (3.0 / distinct).min(1.0),
+            PlanOp::NE => 1.0 - 1.0 / distinct,
+            PlanOp::GT | PlanOp::GE | PlanOp::LT | PlanOp::LE => {
+                match (stats.min, stats.max, value) {
+                    (Some(min), Some(max), Some(value)) if max > min => {
+                        let below = ((value - min) / (max - min)).clamp(0.0, 1.0);
+                        match op {
+                            PlanOp::LT | PlanOp::LE => below,
+                            _ => 1.0 - below,
+                        }
+                    }
+                    _ => 0.25,
+                }
+            }
+            PlanOp::ISNULL => null_fraction,
+            PlanOp::ISNOTNULL => 1.0 - null_fraction,
+            PlanOp::LIKE | PlanOp::GLOB => 0.1,
+        }
+    }
+
+    /// Rows expected from a scan with all of `constraints` applied, never
+    /// below one so SQLite does not mistake a plan for an empty result.
+    pub fn estimate_rows(
+        &self,
+        constraints: impl IntoIterator<Item = (usize, PlanOp, Option<f64>)>,
+    ) -> f64 {
+        constraints
+            .into_iter()
+            .fold(self.rows as f64, |rows, (column, op, value)| {
+                rows * self.selectivity(column, op, value)
+            })
+            .max(1.0)
+    }
+}
+
+/// Smallest and largest value of a numeric column.
+fn numeric_bounds(column: &Column) -> (Option<f64>, Option<f64>) {
+    if !column.dtype().is_primitive_numeric() {
+        return (None, None);
+    }
+    let series = column.as_materialized_series();
+    (
+        series.min::<f64>().ok().flatten(),
+        series.max::<f64>().ok().flatten(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+
+    fn stats() -> TableStats {
+        let df = df!(
+            "state" => ["IL", "TX", "TX", "CA"],
+            "zip" => [Some(60601), None, Some(75201), Some(90001)],
+        )
+        .unwrap();
+        TableStats::from_dataframe(&df)
+    }
+
+    #[test]
+    fn test_from_dataframe() {
+        let stats = stats();
+        assert_eq!(stats.rows, 4);
+        assert_eq!(
+            stats.columns[0],
+            ColumnStats {
+                distinct: 3,
+                nulls: 0,
+                min: None,
+                max: None,
+            }
+        );
+        assert_eq!(stats.columns[1].nulls, 1);
+        assert_eq!(stats.columns[1].min, Some(60601.0));
+        assert_eq!(stats.columns[1].max, Some(90001.0));
+    }
+
+    #[test]
+    fn test_estimate_rows() {
+        let stats = stats();
+        assert_eq!(stats.estimate_rows([]), 4.0);
+        assert!((stats.estimate_rows([(0, PlanOp::EQ, None)]) - 4.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.estimate_rows([(1, PlanOp::ISNULL, None)]), 1.0);
+        assert_eq!(stats.estimate_rows([(1, PlanOp::ISNOTNULL, None)]), 3.0);
+        // Never below a single row.
+        assert_eq!(
+            stats.estimate_rows([(1, PlanOp::EQ, None), (0, PlanOp::EQ, None)]),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_selectivity_range() {
+        let stats = TableStats {
+            rows: 1000,
+            columns: vec![ColumnStats {
+                min: Some(0.0),
+                max: Some(100.0),
+                ..Default::default()
+            }],
+        };
+        assert_eq!(stats.selectivity(0, PlanOp::LT, Some(10.0)), 0.1);
+        assert_eq!(stats.selectivity(0, PlanOp::GE, Some(10.0)), 0.9);
+        assert_eq!(stats.selectivity(0, PlanOp::GT, Some(200.0)), 0.0);
+        assert_eq!(stats.selectivity(0, PlanOp::LE, Some(-5.0)), 0.0);
+        // The value is only known while planning for constants.
+        assert_eq!(stats.selectivity(0, PlanOp::LT, None), 0.25);
+        assert_eq!(stats.estimate_rows([(0, PlanOp::GT, Some(75.0))]), 250.0);
+    }
+
+    #[test]
+    fn test_selectivity_unknown() {
+        let stats = TableStats {
+            rows: 1000,
+            columns: vec![ColumnStats::default()],
+        };
+        assert_eq!(stats.selectivity(0, PlanOp::EQ, None), 0.1);
+        assert_eq!(stats.selectivity(5, PlanOp::EQ, None), 0.1);
+        assert_eq!(stats.selectivity(0, PlanOp::GT, Some(3.0)), 0.25);
+    }
+}
@@ -17,12 +17,17 @@ use sqlite_loadable::{
 pub enum StorageOpts {
     MEM,
     SQLITE,
+    /// Nothing is downloaded up front, every query reads the byte ranges it
+    /// needs from the source. Only available for Parquet.
+    #[allow(clippy::upper_case_acronyms)]
+    REMOTE,
 }
 
 pub fn get_storage(storage: &str) -> Result<StorageOpts, Box<dyn Error>> {
     match storage.trim().to_uppercase().as_str() {
         "MEM" => Ok(StorageOpts::MEM),
         "SQLITE" => Ok(StorageOpts::SQLITE),
+        "REMOTE" => Ok(StorageOpts::REMOTE),
         _ => Err(format!("Not a valid storage option: {}", storage).into()),
     }
 }
@@ -207,6 +212,12 @@ mod tests {
         assert_eq!(result.unwrap(), StorageOpts::SQLITE);
     }
 
+    #[test]
+    fn test_get_storage_remote() {
+        let result = get_storage("remote");
+        assert_eq!(result.unwrap(), StorageOpts::REMOTE);
+    }
+
     #[test]
     fn test_get_storage_invalid() {
         let result = get_storage("disk");
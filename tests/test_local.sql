@@ -0,0 +1,31 @@
+.bail on
+
+.header on
+.mode box
+
+SELECT load_extension('./target/release/libsqlite_httpfs', 'sqlite3_httpfs_init');
+
+CREATE VIRTUAL TABLE IF NOT EXISTS local_demo USING HTTPFS('tests/data/us_cities.csv', 'csv');
+.timer on
+SELECT * FROM local_demo LIMIT 5;
+SELECT * FROM local_demo LIMIT 2 OFFSET 3;
+SELECT * FROM local_demo ORDER BY pop DESC LIMIT 3;
+SELECT * FROM local_demo WHERE name = 'Chicago';
+SELECT * FROM local_demo WHERE name LIKE 'san %';
+SELECT * FROM local_demo WHERE name GLOB 'San [A-D]*';
+PRAGMA case_sensitive_like = true;
+SELECT * FROM local_demo WHERE name LIKE 'san %';
+PRAGMA case_sensitive_like = false;
+SELECT * FROM local_demo WHERE name IN ('Chicago', 'Dallas');
+SELECT count(*) FROM local_demo WHERE pop < 971495.5;
+SELECT count(*) FROM local_demo WHERE pop > 8287237.5;
+SELECT count(*) FROM local_demo WHERE pop != 971495.5;
+SELECT c0 AS name, c1 AS pop FROM httpfs_query('tests/data/us_cities.csv', 'csv', 'SELECT name, pop FROM t WHERE pop > 1000000 ORDER BY pop DESC');
+
+CREATE VIRTUAL TABLE IF NOT EXISTS local_demo_gz USING HTTPFS('tests/data/us_cities.csv.gz', 'csv');
+SELECT count(*) FROM local_demo_gz;
+
+CREATE VIRTUAL TABLE IF NOT EXISTS local_demo_zip USING HTTPFS('tests/data/us_cities.zip', 'csv', member = 'us_cities.csv');
+SELECT count(*) FROM local_demo_zip;
+
+SELECT httpfs_refresh('local_demo');
@@ -0,0 +1,15 @@
+.bail on
+
+.header on
+.mode box
+
+SELECT load_extension('./target/release/libsqlite_httpfs', 'sqlite3_httpfs_init');
+
+CREATE VIRTUAL TABLE IF NOT EXISTS events USING HTTPFS(
+    url = 'tests/data/events/events-*.csv',
+    format = 'csv',
+    source_column = true
+);
+.timer on
+SELECT * FROM events;
+SELECT _source, count(*) FROM events GROUP BY _source;
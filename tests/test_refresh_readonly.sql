@@ -0,0 +1,25 @@
+.bail on
+
+.header on
+.mode box
+
+.shell rm -f target/test_refresh.db target/test_refresh.csv
+.shell cp tests/data/us_cities.csv target/test_refresh.csv
+.open target/test_refresh.db
+SELECT load_extension('./target/release/libsqlite_httpfs', 'sqlite3_httpfs_init');
+
+CREATE VIRTUAL TABLE cities USING HTTPFS('target/test_refresh.csv', 'csv', refresh = true);
+SELECT count(*) FROM cities;
+
+-- Modification times have a resolution of one second.
+.shell sleep 1 && echo "Springfield,114394,39.78,-89.65" >> target/test_refresh.csv
+
+-- The shadow tables can't be rewritten, the new data is still read.
+.open --readonly target/test_refresh.db
+SELECT load_extension('./target/release/libsqlite_httpfs', 'sqlite3_httpfs_init');
+SELECT count(*) AS cities, (SELECT count(*) FROM "httpfs.cities_data") AS stored FROM cities;
+SELECT * FROM cities WHERE name = 'Springfield';
+
+.open target/test_refresh.db
+SELECT load_extension('./target/release/libsqlite_httpfs', 'sqlite3_httpfs_init');
+SELECT count(*) AS cities, (SELECT count(*) FROM "httpfs.cities_data") AS stored FROM cities;